@@ -7,12 +7,14 @@ fn main() {
         Windows::Win32::Graphics::DirectWrite::*,
         Windows::Win32::Graphics::DirectComposition::*,
         Windows::Win32::System::Com::*,
+        Windows::Win32::System::Console::*,
         Windows::Win32::System::Diagnostics::Debug::*,
         Windows::Win32::System::OleAutomation::*,
         Windows::Win32::System::SystemServices::*,
         Windows::Win32::System::LibraryLoader::*,
         Windows::Win32::System::Threading::*,
         Windows::Win32::UI::Animation::*,
+        Windows::Win32::UI::KeyboardAndMouseInput::*,
         Windows::Win32::UI::Shell::*,
         Windows::Win32::UI::WindowsAndMessaging::*,
     };
@@ -0,0 +1,364 @@
+use std::fs;
+use std::io::stdout;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use bindings::Windows::Win32::System::Console::*;
+
+use crate::config::Config;
+use crate::lyrics::export;
+use crate::lyrics::format;
+use crate::lyrics::format::Format;
+use crate::lyrics::tags::read_lyrics;
+use crate::lyrics::tags::read_track;
+use crate::lyrics::tags::write_lyrics;
+use crate::lyrics::Lyrics;
+use crate::lyrics::LyricsError;
+use crate::lyrics::OffsetStore;
+use crate::lyrics::Query;
+use crate::lyrics::Track;
+use crate::paths::offsets_file;
+use crate::player::itunes::ITunes;
+
+const USAGE: &str = "Usage:
+  ilyrics export [--format lrc|srt|vtt|ass|txt] [--output <file>] <lyrics or audio file>
+  ilyrics export [--format lrc|srt|vtt|ass|txt] [--output <file>] --current
+  ilyrics export [--format lrc|srt|vtt|ass|txt] [--output <file>] --artist <artist> --name <name>
+
+Exports lyrics as LRC, subtitles or plain text: those of a local file or
+embedded in an audio file, of the track playing in iTunes, or of any track.
+The format defaults to the extension of the output file, or SRT. Without
+--output, the lyrics are written to the console. Plain text lyrics are given
+estimated times.
+
+  ilyrics embed [--dry-run] [--overwrite] [--lyrics <file>] <audio file or folder>
+  ilyrics embed [--dry-run] [--overwrite] --current
+
+Embeds lyrics into the tags of audio files so that other players and devices
+show them too: those of a lyrics file, or those looked up by the tags of each
+file, moved by the offset set for the track. Folders are searched along with
+their subfolders. Files with lyrics are skipped unless --overwrite is given,
+and so are files for which no lyrics match the tags well, unless a lyrics
+file is given or the lyrics have been retimed. With --dry-run, the files are
+left untouched.";
+
+/// Attaches to the console of the parent process, `(DWORD)-1` in the Windows
+/// headers, which the metadata of the bindings leaves out.
+const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+/// The extensions of the audio files to embed lyrics into in folders.
+const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "flac", "m4a", "mp4"];
+
+/// Runs the command given on the command line instead of the lyrics window.
+pub fn run(args: &[String]) -> Result<()> {
+    // Windows doesn't give the lyrics window a console, so borrow the one
+    // we were started from to print to.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+    match args.first().map(|command| command.as_str()) {
+        Some("export") => run_export(&args[1..]),
+        Some("embed") => run_embed(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => bail!("{}", USAGE),
+    }
+}
+
+/// Where the lyrics to export come from.
+enum Source {
+    File(PathBuf),
+    Current,
+    Track { artist: String, name: String },
+}
+
+fn run_export(args: &[String]) -> Result<()> {
+    let mut format = None;
+    let mut output = None;
+    let mut file = None;
+    let mut current = false;
+    let mut artist = None;
+    let mut name = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing the value of {}\n\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--format" => format = Some(parse_format(&value()?)?),
+            "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+            "--current" => current = true,
+            "--artist" => artist = Some(value()?),
+            "--name" => name = Some(value()?),
+            arg if arg.starts_with('-') => bail!("Unknown option {}\n\n{}", arg, USAGE),
+            _ => file = Some(PathBuf::from(arg)),
+        }
+    }
+    let source = match (file, current, artist, name) {
+        (Some(file), false, None, None) => Source::File(file),
+        (None, true, None, None) => Source::Current,
+        (None, false, Some(artist), Some(name)) => Source::Track { artist, name },
+        _ => bail!("{}", USAGE),
+    };
+    let format = match (format, output.as_ref()) {
+        (Some(format), _) => format,
+        (None, Some(output)) => output
+            .extension()
+            .and_then(|extension| Format::from_extension(&extension.to_string_lossy()))
+            .unwrap_or(Format::Srt),
+        (None, None) => Format::Srt,
+    };
+    let (lyrics, user_offset) = load(source)?;
+    let subtitles = export(&lyrics, format, user_offset)?;
+    match output {
+        Some(output) => fs::write(&output, subtitles)
+            .with_context(|| format!("Failed to write {}", output.display()))?,
+        None => stdout().write_all(subtitles.as_bytes())?,
+    }
+    Ok(())
+}
+
+fn parse_format(name: &str) -> Result<Format> {
+    match Format::from_extension(name) {
+        Some(Format::Ttml) | None => bail!("Unknown format {}\n\n{}", name, USAGE),
+        Some(format) => Ok(format),
+    }
+}
+
+/// Loads the lyrics, along with the offset the user has set for the track.
+fn load(source: Source) -> Result<(Lyrics, i64)> {
+    let track = match source {
+        Source::File(path) => return Ok((load_file(&path)?, 0)),
+        Source::Current => current_track()?,
+        Source::Track { artist, name } => Track {
+            name,
+            artist,
+            album: String::new(),
+            album_artist: String::new(),
+            duration: None,
+            location: None,
+        },
+    };
+    let found = Library::new(Config::load()?)?
+        .get_lyrics(&track)
+        .with_context(|| format!("No lyrics for {} - {}", track.artist, track.name))?;
+    Ok(found)
+}
+
+fn current_track() -> Result<Track> {
+    let info = ITunes::new()?
+        .get_current_track_info()
+        .ok_or_else(|| anyhow!("No track in iTunes"))?;
+    Ok(Track {
+        name: info.name,
+        artist: info.artist,
+        album: info.album,
+        album_artist: info.album_artist,
+        duration: info.duration,
+        location: info.location,
+    })
+}
+
+/// Looks up lyrics as the lyrics window does.
+struct Library {
+    query: Query,
+    offsets: OffsetStore,
+}
+
+impl Library {
+    fn new(config: Config) -> Result<Self> {
+        Ok(Self {
+            query: Query::new(&config, || {})?,
+            offsets: OffsetStore::load(offsets_file()),
+        })
+    }
+
+    /// The lyrics of `track`, served from the cache if it has been played
+    /// before, along with the offset the user has set for it.
+    fn get_lyrics(&self, track: &Track) -> Result<(Lyrics, i64), LyricsError> {
+        let lyrics = self.query.get_lyrics(track)?;
+        Ok((lyrics, self.offsets.get(&track.key())))
+    }
+}
+
+fn run_embed(args: &[String]) -> Result<()> {
+    let mut dry_run = false;
+    let mut overwrite = false;
+    let mut current = false;
+    let mut lyrics_file = None;
+    let mut target = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing the value of {}\n\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--dry-run" | "-n" => dry_run = true,
+            "--overwrite" => overwrite = true,
+            "--current" => current = true,
+            "--lyrics" => lyrics_file = Some(PathBuf::from(value()?)),
+            arg if arg.starts_with('-') => bail!("Unknown option {}\n\n{}", arg, USAGE),
+            _ => target = Some(PathBuf::from(arg)),
+        }
+    }
+    let files = match (target, current) {
+        (Some(target), false) if target.is_dir() => {
+            if lyrics_file.is_some() {
+                bail!("--lyrics takes a single audio file\n\n{}", USAGE);
+            }
+            let mut files = vec![];
+            find_audio_files(&target, &mut files)?;
+            files.into_iter().map(|file| (file, None)).collect()
+        }
+        (Some(target), false) => vec![(target, None)],
+        (None, true) => {
+            let track = current_track()?;
+            let location = track
+                .location
+                .clone()
+                .ok_or_else(|| anyhow!("{} - {} is not an audio file", track.artist, track.name))?;
+            vec![(location, Some(track))]
+        }
+        _ => bail!("{}", USAGE),
+    };
+    let source = match lyrics_file {
+        Some(lyrics_file) => LyricsSource::File(load_file(&lyrics_file)?),
+        None => {
+            let mut config = Config::load()?;
+            // Otherwise the lyrics being replaced would be found again.
+            config
+                .lyrics
+                .providers
+                .retain(|provider| provider != "embedded");
+            LyricsSource::Library(Library::new(config)?)
+        }
+    };
+    let mut failed = 0;
+    for (file, track) in files.iter() {
+        match embed(file, track.as_ref(), &source, dry_run, overwrite) {
+            Ok(report) => println!("{}: {}", file.display(), report),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {:#}", file.display(), e);
+            }
+        }
+    }
+    if current && !dry_run && failed == 0 {
+        // iTunes keeps its own copy of the tags.
+        ITunes::new()?.update_current_track_from_file()?;
+    }
+    if failed > 0 {
+        bail!(
+            "Failed to embed lyrics into {} of {} files",
+            failed,
+            files.len()
+        );
+    }
+    Ok(())
+}
+
+/// Where the lyrics to embed come from.
+enum LyricsSource {
+    File(Lyrics),
+    Library(Library),
+}
+
+/// Embeds lyrics into the audio file at `path`, which is `track` if known,
+/// and tells what was done.
+fn embed(
+    path: &Path,
+    track: Option<&Track>,
+    source: &LyricsSource,
+    dry_run: bool,
+    overwrite: bool,
+) -> Result<String> {
+    if !overwrite && read_lyrics(path)?.is_some() {
+        return Ok("skipped, it has lyrics".to_string());
+    }
+    let (lyrics, user_offset) = match source {
+        LyricsSource::File(lyrics) => (lyrics.clone(), 0),
+        LyricsSource::Library(library) => {
+            let track = match track {
+                Some(track) => track.clone(),
+                None => match read_track(path)? {
+                    Some(track) => track,
+                    None => return Ok("skipped, it has no title or artist".to_string()),
+                },
+            };
+            match library.get_lyrics(&track) {
+                // Likely those of another song, which would stay in the tags.
+                Ok((lyrics, _)) if lyrics.metadata().poor_match => {
+                    return Ok("skipped, no lyrics match it well".to_string())
+                }
+                Ok(found) => found,
+                Err(LyricsError::NotFound) => return Ok("no lyrics found".to_string()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+    let fields = write_lyrics(path, &lyrics, user_offset, dry_run)?.join(", ");
+    Ok(if dry_run {
+        format!("would write {}", fields)
+    } else {
+        format!("wrote {}", fields)
+    })
+}
+
+/// Adds the audio files in `directory` and its subdirectories to `files`, in
+/// order.
+fn find_audio_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(directory)
+        .with_context(|| format!("Failed to read {}", directory.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_audio_files(&path, files)?;
+        } else if path
+            .extension()
+            .map(|extension| {
+                AUDIO_EXTENSIONS
+                    .iter()
+                    .any(|audio| extension.eq_ignore_ascii_case(audio))
+            })
+            .unwrap_or(false)
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn load_file(path: &Path) -> Result<Lyrics> {
+    let is_lyrics = path
+        .extension()
+        .and_then(|extension| Format::from_extension(&extension.to_string_lossy()))
+        .is_some();
+    let content = if is_lyrics {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?
+    } else {
+        read_lyrics(path)
+            .with_context(|| format!("Failed to read the tags of {}", path.display()))?
+            .ok_or_else(|| anyhow!("No lyrics in {}", path.display()))?
+    };
+    let parsed = format::parse(&content);
+    for diagnostic in parsed.diagnostics.iter() {
+        eprintln!("Skipped {}", diagnostic);
+    }
+    if parsed.lyrics.is_empty() {
+        bail!("No timed lines in {}", path.display());
+    }
+    Ok(parsed.lyrics)
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use log::info;
+use serde::Deserialize;
+
+use crate::paths::config_file;
+
+/// The user configuration, read from `config.toml` in the data directory.
+///
+/// Every field is optional; see `README.md` for an example.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub lyrics: LyricsConfig,
+    pub lyrics_api: LyricsApiConfig,
+    pub http: HttpConfig,
+    pub display: DisplayConfig,
+    pub hotkeys: HotkeysConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LyricsConfig {
+    /// The names of the providers to ask, in order.
+    pub providers: Vec<String>,
+    /// The script to convert Chinese lyrics to, if any.
+    pub chinese: Option<ChineseScript>,
+    /// A folder of lyrics files for the `local` provider, which also finds
+    /// files next to the audio files.
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for LyricsConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec![
+                "local".to_string(),
+                "embedded".to_string(),
+                "lyrics-api".to_string(),
+            ],
+            chinese: None,
+            directory: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChineseScript {
+    Simplified,
+    Traditional,
+    /// Traditional characters with the vocabulary of Taiwan.
+    Taiwan,
+    /// Traditional characters with the variants of Hong Kong.
+    HongKong,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LyricsApiConfig {
+    pub endpoint: String,
+}
+
+impl Default for LyricsApiConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://lyrics-api.lujjjh.com/".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// The timeout of each request in seconds.
+    pub timeout: u64,
+    /// The proxy of all requests, e.g. `http://proxy.example.com:8080`.
+    pub proxy: Option<String>,
+    /// A PEM file of extra root certificates to trust.
+    pub ca_bundle: Option<PathBuf>,
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every request.
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 10,
+            proxy: None,
+            ca_bundle: None,
+            user_agent: None,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Which texts of bilingual lyrics to show.
+    pub lines: DisplayLines,
+    /// Where to show the romanization of Chinese, Japanese and Korean lyrics.
+    pub romanization: RomanizationDisplay,
+    /// Silences between lines no longer than this, in seconds, are bridged by
+    /// the line before them. Longer ones are gaps, e.g. intros and solos.
+    pub min_gap: f64,
+    /// What to show during gaps instead of nothing.
+    pub gap_indicator: GapIndicator,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            lines: Default::default(),
+            romanization: Default::default(),
+            min_gap: 3.,
+            gap_indicator: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayLines {
+    Original,
+    /// The translation where there is one, the original elsewhere.
+    Translation,
+    /// The original with the translation below it.
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RomanizationDisplay {
+    #[default]
+    Off,
+    /// Above the line in a smaller font, like ruby.
+    Ruby,
+    /// Below the line, like a translation.
+    Line,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GapIndicator {
+    #[default]
+    Off,
+    /// A note, `♪`.
+    Note,
+    /// Dots that fill up until the next line.
+    Dots,
+    /// The seconds left until the next line, each filling up as it passes.
+    Countdown,
+}
+
+/// System-wide hotkeys, written like `Ctrl+Alt+Shift+Left`: any of `Ctrl`,
+/// `Alt`, `Shift` and `Win`, then a letter, a digit, `F1` to `F24` or the
+/// name of a key such as `Space`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HotkeysConfig {
+    /// Shows the lyrics earlier, or the line being shown while syncing by
+    /// hand.
+    pub offset_earlier: String,
+    pub offset_later: String,
+    pub offset_reset: String,
+    /// Takes the line on the overlay as being sung right now.
+    pub anchor: String,
+    /// Discards the corrected lyrics, or cancels syncing by hand.
+    pub discard: String,
+    /// Starts syncing lyrics by hand, and saves them.
+    pub sync: String,
+    /// Only taken while syncing by hand, like `undo`.
+    pub tap: String,
+    pub undo: String,
+}
+
+impl Default for HotkeysConfig {
+    fn default() -> Self {
+        // Ctrl+Alt and an arrow rotate the screen with some graphics drivers.
+        Self {
+            offset_earlier: "Ctrl+Alt+Shift+Left".to_string(),
+            offset_later: "Ctrl+Alt+Shift+Right".to_string(),
+            offset_reset: "Ctrl+Alt+0".to_string(),
+            anchor: "Ctrl+Alt+Shift+Up".to_string(),
+            discard: "Ctrl+Alt+Shift+Down".to_string(),
+            sync: "Ctrl+Alt+S".to_string(),
+            tap: "Ctrl+Alt+Space".to_string(),
+            undo: "Ctrl+Alt+Backspace".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the path in the `ILYRICS_CONFIG`
+    /// environment variable, or from the default location.
+    ///
+    /// A missing file is not an error and yields the defaults.
+    pub fn load() -> Result<Self> {
+        let path = env::var_os("ILYRICS_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(config_file);
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        info!("Loading config from {}", path.display());
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
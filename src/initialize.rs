@@ -1,11 +1,9 @@
-use std::path::Path;
 use std::process::exit;
 use std::ptr::null_mut;
 
 use bindings::Windows::Win32::System::Com::*;
 use bindings::Windows::Win32::System::Diagnostics::Debug::*;
 use bindings::Windows::Win32::System::Threading::*;
-use bindings::Windows::Win32::UI::Shell::*;
 use bindings::Windows::Win32::UI::WindowsAndMessaging::*;
 use flexi_logger::detailed_format;
 use flexi_logger::Cleanup;
@@ -15,6 +13,8 @@ use flexi_logger::Logger;
 use flexi_logger::Naming;
 use windows::*;
 
+use crate::paths::data_directory;
+
 #[cfg(debug_assertions)]
 const UUID: &str = "c727e9a0-c71a-4001-ad0b-be20fd8e7971";
 
@@ -37,10 +37,7 @@ pub fn initialize() -> Result<()> {
             exit(1);
         }
 
-        let roaming_app_data_path = known_folder_path(&FOLDERID_RoamingAppData)?;
-        let log_directory = Path::new(&roaming_app_data_path)
-            .join("iLyrics")
-            .join("logs");
+        let log_directory = data_directory().join("logs");
         Logger::try_with_str("info")
             .unwrap()
             .log_to_file(
@@ -60,22 +57,3 @@ pub fn initialize() -> Result<()> {
         Ok(())
     }
 }
-
-fn known_folder_path(id: &windows::Guid) -> Result<String> {
-    unsafe {
-        let path = SHGetKnownFolderPath(id, 0, None)?;
-        if path.0.is_null() {
-            return Ok(String::new());
-        }
-        let mut end = path.0;
-        while *end != 0 {
-            end = end.add(1);
-        }
-        let result = String::from_utf16_lossy(std::slice::from_raw_parts(
-            path.0,
-            end.offset_from(path.0) as _,
-        ));
-        CoTaskMemFree(path.0 as _);
-        Ok(result)
-    }
-}
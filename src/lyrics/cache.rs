@@ -0,0 +1,212 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+use log::info;
+use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::format::Format;
+use super::FetchedLyrics;
+use super::QueryVariant;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_MAX_ENTRIES: usize = 5000;
+const DEFAULT_MAX_SIZE: u64 = 50 * 1024 * 1024;
+
+/// A cached lyrics document, or the fact that no provider has one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// The normalized identity of the track, see [`Track::key`](super::Track::key).
+    pub key: String,
+    pub provider: String,
+    /// Seconds since the Unix epoch.
+    pub fetched_at: u64,
+    /// `None` if the lyrics were not found.
+    pub lyrics: Option<String>,
+    /// The parallel translation document of the lyrics, if any.
+    #[serde(default)]
+    pub translation: Option<String>,
+    /// The query that found the lyrics, tried first once the entry expires.
+    pub variant: Option<QueryVariant>,
+    /// See [`FetchedLyrics::poor_match`].
+    #[serde(default)]
+    pub poor_match: bool,
+}
+
+impl CacheEntry {
+    pub fn new(key: &str, fetched: &FetchedLyrics, variant: &QueryVariant) -> Self {
+        Self {
+            key: key.to_string(),
+            provider: fetched.provider.clone(),
+            fetched_at: unix_time(),
+            lyrics: Some(fetched.content.clone()),
+            translation: fetched.translation.clone(),
+            variant: Some(variant.clone()),
+            poor_match: fetched.poor_match,
+        }
+    }
+
+    pub fn not_found(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            provider: String::new(),
+            fetched_at: unix_time(),
+            lyrics: None,
+            translation: None,
+            variant: None,
+            poor_match: false,
+        }
+    }
+
+    /// Whether the entry holds synced lyrics, rather than plain text or the
+    /// fact that there are none.
+    pub fn is_synced(&self) -> bool {
+        self.lyrics
+            .as_deref()
+            .map(|lyrics| Format::detect(lyrics) != Format::Plain)
+            .unwrap_or(false)
+    }
+
+    fn age(&self) -> Duration {
+        Duration::from_secs(unix_time().saturating_sub(self.fetched_at))
+    }
+}
+
+/// An on-disk cache of lyrics documents, one JSON file per track.
+///
+/// Entries expire after a TTL, and "not found" and plain text entries after a
+/// shorter one so that lyrics uploaded later are eventually picked up. Once the cache grows
+/// beyond its limits, the least recently written entries are evicted.
+pub struct LyricsCache {
+    directory: PathBuf,
+    ttl: Duration,
+    negative_ttl: Duration,
+    max_entries: usize,
+    max_size: u64,
+}
+
+impl LyricsCache {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            ttl: DEFAULT_TTL,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Returns the entry of `key` unless it is missing, unreadable or expired.
+    pub fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.peek(key).filter(|entry| {
+            // Plain text is looked up again as often as missing lyrics, in
+            // case synced lyrics have been uploaded since.
+            let ttl = if entry.is_synced() {
+                self.ttl
+            } else {
+                self.negative_ttl
+            };
+            entry.age() <= ttl
+        })
+    }
+
+    /// Returns the entry of `key` even if it has expired.
+    pub fn peek(&self, key: &str) -> Option<CacheEntry> {
+        let path = self.entry_path(key);
+        let content = fs::read_to_string(&path).ok()?;
+        let entry: CacheEntry = match serde_json::from_str(&content) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Corrupted cache entry {}: {:?}", path.display(), e);
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+        };
+        // Different keys may share the same file name.
+        if entry.key != key {
+            return None;
+        }
+        Some(entry)
+    }
+
+    pub fn put(&self, entry: &CacheEntry) -> Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let content = serde_json::to_string(entry)?;
+        fs::write(self.entry_path(&entry.key), content)?;
+        self.evict()
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.directory
+            .join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+    }
+
+    fn evict(&self) -> Result<()> {
+        let mut files = vec![];
+        for dir_entry in fs::read_dir(&self.directory)? {
+            let path = dir_entry?.path();
+            // Other files may share the directory.
+            if !is_entry_path(&path) {
+                continue;
+            }
+            let metadata = fs::metadata(&path)?;
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            files.push((modified, metadata.len(), path));
+        }
+        // Newest first, so that everything past the limits can be dropped.
+        files.sort_by_key(|file| Reverse(file.0));
+        let now = SystemTime::now();
+        let mut count = 0;
+        let mut size = 0;
+        for (modified, len, path) in files {
+            let expired = now.duration_since(modified).unwrap_or_default() > self.ttl;
+            if expired || count >= self.max_entries || size + len > self.max_size {
+                info!("Evicting cache entry {}", path.display());
+                remove_file(&path);
+                continue;
+            }
+            count += 1;
+            size += len;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` is named like an entry, see [`LyricsCache::entry_path`].
+fn is_entry_path(path: &Path) -> bool {
+    let is_hash = |stem: &str| stem.len() == 16 && stem.bytes().all(|b| b.is_ascii_hexdigit());
+    path.extension().map(|ext| ext == "json").unwrap_or(false)
+        && path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(is_hash)
+            .unwrap_or(false)
+}
+
+fn remove_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("Failed to remove {}: {:?}", path.display(), e);
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A stable hash for file names, unlike `DefaultHasher` whose algorithm may change.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,144 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use ferrous_opencc::config::BuiltinConfig;
+use ferrous_opencc::OpenCC;
+
+use super::romanize::Script;
+use super::Line;
+use super::Word;
+use crate::config::ChineseScript;
+
+/// Converts Chinese lyrics between Simplified and Traditional characters with
+/// the phrase dictionaries of OpenCC, so that e.g. 头发 becomes 頭髮 rather
+/// than 頭發.
+pub struct ChineseConverter {
+    opencc: OpenCC,
+}
+
+impl ChineseConverter {
+    pub fn new(script: ChineseScript) -> Result<Self> {
+        let config = match script {
+            ChineseScript::Simplified => BuiltinConfig::T2s,
+            ChineseScript::Traditional => BuiltinConfig::S2t,
+            // Also uses the words of Taiwan, e.g. 軟體 for 软件.
+            ChineseScript::Taiwan => BuiltinConfig::S2twp,
+            ChineseScript::HongKong => BuiltinConfig::S2hk,
+        };
+        let opencc = OpenCC::from_config(config)
+            .map_err(|e| anyhow!("Failed to load the Chinese dictionaries: {:?}", e))?;
+        Ok(Self { opencc })
+    }
+
+    /// Converts the texts of `line` if `script`, that of the whole lyrics, is
+    /// Chinese, and its translation if `translation_script` is. The kanji of
+    /// Japanese lyrics are neither Simplified nor Traditional, and are left
+    /// alone.
+    pub fn convert_line(
+        &self,
+        line: Line,
+        script: Option<Script>,
+        translation_script: Option<Script>,
+    ) -> Line {
+        let translation = match translation_script {
+            Some(Script::Chinese) => line
+                .translation
+                .as_deref()
+                .map(|text| self.opencc.convert(text)),
+            _ => line.translation.clone(),
+        };
+        let mut converted = if script == Some(Script::Chinese) {
+            let mut converted = line.map_text(|text| self.opencc.convert(text));
+            if !line.words.is_empty() {
+                converted.set_words(self.convert_words(&line));
+            }
+            converted
+        } else {
+            line
+        };
+        converted.translation = translation;
+        converted
+    }
+
+    /// Converts the words of `line` as a whole, so that phrases spanning
+    /// several words are still recognized, unless that changes the number of
+    /// characters; then each word is converted on its own.
+    fn convert_words(&self, line: &Line) -> Vec<Word> {
+        let converted = self.opencc.convert(&line.text);
+        if converted.chars().count() != line.text.chars().count() {
+            return line
+                .words
+                .iter()
+                .map(|word| Word::new(word.time, &self.opencc.convert(&word.text)))
+                .collect();
+        }
+        let mut chars = converted.chars();
+        line.words
+            .iter()
+            .map(|word| {
+                let text = chars
+                    .by_ref()
+                    .take(word.text.chars().count())
+                    .collect::<String>();
+                Word::new(word.time, &text)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn words(texts: &[&str]) -> Line {
+        Line::with_words(
+            Duration::ZERO,
+            texts
+                .iter()
+                .enumerate()
+                .map(|(i, text)| Word::new(Duration::from_secs(i as u64), text))
+                .collect(),
+        )
+    }
+
+    fn word_texts(line: &Line) -> Vec<&str> {
+        line.words.iter().map(|word| word.text.as_str()).collect()
+    }
+
+    #[test]
+    fn converts_phrases() {
+        let converter = ChineseConverter::new(ChineseScript::Traditional).unwrap();
+        let line = Line::new(Duration::ZERO, "头发干燥，干涉");
+        let line = converter.convert_line(line, Some(Script::Chinese), None);
+        assert_eq!(line.text, "頭髮乾燥，干涉");
+    }
+
+    #[test]
+    fn converts_phrases_spanning_words() {
+        let converter = ChineseConverter::new(ChineseScript::Traditional).unwrap();
+        let line = converter.convert_line(words(&["头", "发"]), Some(Script::Chinese), None);
+        assert_eq!(word_texts(&line), ["頭", "髮"]);
+        assert_eq!(line.text, "頭髮");
+        assert_eq!(line.words[1].time, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn converts_words_on_their_own_if_phrases_change_length() {
+        // 隨身碟 in Taiwan, which doesn't split into the two words.
+        let converter = ChineseConverter::new(ChineseScript::Taiwan).unwrap();
+        let line = converter.convert_line(words(&["我的", "U", "盘"]), Some(Script::Chinese), None);
+        assert_eq!(word_texts(&line), ["我的", "U", "盤"]);
+        assert_eq!(line.text, "我的U盤");
+    }
+
+    #[test]
+    fn converts_chinese_translations_of_other_lyrics() {
+        let converter = ChineseConverter::new(ChineseScript::Traditional).unwrap();
+        let mut line = Line::new(Duration::ZERO, "髪が乾いた");
+        line.translation = Some("头发干燥".to_string());
+        let line = converter.convert_line(line, Some(Script::Japanese), Some(Script::Chinese));
+        assert_eq!(line.text, "髪が乾いた");
+        assert_eq!(line.translation.as_deref(), Some("頭髮乾燥"));
+    }
+}
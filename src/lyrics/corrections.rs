@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::cache::fnv1a;
+use super::Lyrics;
+
+/// Lyrics retimed by the user, one JSON file per track key (see
+/// [`Track::key`](super::Track::key)). They take precedence over lyrics from
+/// anywhere else.
+pub struct CorrectionStore {
+    directory: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Correction {
+    key: String,
+    /// The lyrics as a whole rather than as a document, which would lose
+    /// what its format can't hold, e.g. the background vocals of TTML.
+    lyrics: Lyrics,
+}
+
+impl CorrectionStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// Returns the corrected lyrics of `key`, if any.
+    pub fn get(&self, key: &str) -> Option<Lyrics> {
+        let path = self.path(key);
+        let content = fs::read_to_string(&path).ok()?;
+        let correction: Correction = match serde_json::from_str(&content) {
+            Ok(correction) => correction,
+            Err(e) => {
+                warn!("Corrupted {}: {:?}", path.display(), e);
+                return None;
+            }
+        };
+        // Different keys may share the same file name.
+        if correction.key != key {
+            return None;
+        }
+        Some(correction.lyrics)
+    }
+
+    /// Saves `lyrics` as the corrected lyrics of `key`, or removes them if
+    /// `None`.
+    pub fn set(&self, key: &str, lyrics: Option<&Lyrics>) -> Result<()> {
+        let path = self.path(key);
+        let lyrics = match lyrics {
+            Some(lyrics) => lyrics,
+            None => {
+                if path.exists() {
+                    fs::remove_file(&path)?;
+                }
+                return Ok(());
+            }
+        };
+        let correction = Correction {
+            key: key.to_string(),
+            lyrics: lyrics.clone(),
+        };
+        fs::create_dir_all(&self.directory)?;
+        fs::write(&path, serde_json::to_string(&correction)?)?;
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory
+            .join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::lyrics::Line;
+    use crate::lyrics::Word;
+
+    #[test]
+    fn keeps_what_lrc_cannot_hold() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = CorrectionStore::new(directory.path().to_path_buf());
+        let mut line = Line::new(Duration::from_secs(1), "頭髮");
+        line.end = Some(Duration::from_secs(3));
+        line.agent = Some(String::from("v2"));
+        line.background = vec![Word::new(Duration::from_secs(2), "ooh")];
+        let lyrics = Lyrics::new(vec![line]);
+        store.set("key", Some(&lyrics)).unwrap();
+        assert_eq!(store.get("key"), Some(lyrics));
+        assert_eq!(store.get("other key"), None);
+        store.set("key", None).unwrap();
+        assert_eq!(store.get("key"), None);
+    }
+}
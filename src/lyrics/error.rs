@@ -0,0 +1,44 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why a lyrics lookup did not produce any lyrics.
+#[derive(Debug)]
+pub enum LyricsError {
+    /// No provider has lyrics for the track.
+    NotFound,
+    /// A network failure or a server-side error that may go away on retry.
+    Transient(anyhow::Error),
+    /// The document was fetched but could not be parsed.
+    Parse(anyhow::Error),
+}
+
+impl LyricsError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, LyricsError::Transient(_))
+    }
+}
+
+impl fmt::Display for LyricsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LyricsError::NotFound => write!(f, "lyrics not found"),
+            LyricsError::Transient(e) => write!(f, "transient error: {}", e),
+            LyricsError::Parse(e) => write!(f, "failed to parse lyrics: {}", e),
+        }
+    }
+}
+
+impl Error for LyricsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LyricsError::NotFound => None,
+            LyricsError::Transient(e) | LyricsError::Parse(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for LyricsError {
+    fn from(e: reqwest::Error) -> Self {
+        LyricsError::Transient(e.into())
+    }
+}
@@ -0,0 +1,418 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use super::format::Format;
+use super::shift;
+use super::Line;
+use super::Lyrics;
+
+/// How long the last line lasts if neither it nor the document says so.
+const LAST_LINE_DURATION: Duration = Duration::from_secs(5);
+
+const ASS_HEADER: &str = "[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,64,&H00FFFFFF,&H00808080,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,3,0,2,60,60,60,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+
+/// A line of lyrics as a subtitle, in the time of the recording.
+struct Cue {
+    start: Duration,
+    end: Duration,
+    line: Line,
+}
+
+/// Writes `lyrics` as LRC, or as subtitles in `format`, e.g. for lyric videos.
+///
+/// Times are moved by the `[offset:]` of the document and by `user_offset`,
+/// in milliseconds, so that they match the recording. In subtitles, a line
+/// without an end lasts until the next one starts. Word timings become
+/// karaoke tags in WebVTT and ASS, and word tags in enhanced LRC. Plain text
+/// has no times, and gaps between lines become blank lines.
+pub fn export(lyrics: &Lyrics, format: Format, user_offset: i64) -> Result<String> {
+    match format {
+        Format::Lrc => return Ok(to_lrc(lyrics, user_offset)),
+        Format::Plain => return Ok(to_plain(lyrics)),
+        _ => {}
+    }
+    let cues = cues(lyrics, user_offset);
+    Ok(match format {
+        Format::Srt => to_srt(&cues),
+        Format::WebVtt => to_vtt(&cues),
+        Format::Ass => to_ass(&cues, lyrics.metadata().title.as_deref()),
+        _ => bail!("Can't export lyrics as {:?}", format),
+    })
+}
+
+fn cues(lyrics: &Lyrics, user_offset: i64) -> Vec<Cue> {
+    // The inverse of `Metadata::apply_offset`.
+    let offset = -(lyrics.metadata().offset + user_offset);
+    let lines = lyrics.lines();
+    let mut cues = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        // Empty lines only mark where the previous line ends.
+        if line.text.is_empty() {
+            continue;
+        }
+        let end = line
+            .end
+            .or_else(|| lines.get(i + 1).map(|next| next.time))
+            .or(lyrics.metadata().length)
+            .filter(|end| *end > line.time)
+            .unwrap_or(line.time + LAST_LINE_DURATION);
+        let mut line = line.clone();
+        for word in line.words.iter_mut() {
+            word.time = shift(word.time, offset);
+        }
+        cues.push(Cue {
+            start: shift(line.time, offset),
+            end: shift(end, offset),
+            line,
+        });
+    }
+    cues
+}
+
+fn to_lrc(lyrics: &Lyrics, user_offset: i64) -> String {
+    let offset = -(lyrics.metadata().offset + user_offset);
+    let metadata = lyrics.metadata();
+    let mut lrc = String::new();
+    let tags = [
+        ("ti", metadata.title.as_ref()),
+        ("ar", metadata.artist.as_ref()),
+        ("al", metadata.album.as_ref()),
+        ("by", metadata.by.as_ref()),
+    ];
+    for (tag, value) in tags.iter() {
+        if let Some(value) = value {
+            writeln!(lrc, "[{}:{}]", tag, value).unwrap();
+        }
+    }
+    let lines = lyrics.lines();
+    for (i, line) in lines.iter().enumerate() {
+        let time = format_lrc_timestamp(shift(line.time, offset));
+        write!(lrc, "[{}]", time).unwrap();
+        if line.words.is_empty() {
+            lrc.push_str(&line.text);
+        }
+        for word in line.words.iter() {
+            let word_time = format_lrc_timestamp(shift(word.time, offset));
+            write!(lrc, "<{}>{}", word_time, word.text).unwrap();
+        }
+        writeln!(lrc).unwrap();
+        // Like bilingual LRC, with the translation at the same time.
+        if let Some(translation) = line.translation.as_ref() {
+            writeln!(lrc, "[{}]{}", time, translation).unwrap();
+        }
+        // An empty line marks an end that comes before the next line.
+        if let Some(end) = line.end {
+            if lines.get(i + 1).map(|next| next.time > end).unwrap_or(true) {
+                writeln!(lrc, "[{}]", format_lrc_timestamp(shift(end, offset))).unwrap();
+            }
+        }
+    }
+    lrc
+}
+
+fn to_plain(lyrics: &Lyrics) -> String {
+    let mut text = String::new();
+    for line in lyrics.lines().iter() {
+        if !line.text.is_empty() {
+            writeln!(text, "{}", line.text).unwrap();
+        } else if !text.is_empty() && !text.ends_with("\n\n") {
+            writeln!(text).unwrap();
+        }
+    }
+    text.truncate(text.trim_end().len());
+    text
+}
+
+fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        writeln!(srt, "{}", i + 1).unwrap();
+        writeln!(
+            srt,
+            "{} --> {}",
+            format_timestamp(cue.start, ','),
+            format_timestamp(cue.end, ',')
+        )
+        .unwrap();
+        writeln!(srt, "{}", cue.line.text).unwrap();
+        if let Some(translation) = cue.line.translation.as_ref() {
+            writeln!(srt, "{}", translation).unwrap();
+        }
+        writeln!(srt).unwrap();
+    }
+    srt
+}
+
+fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues.iter() {
+        writeln!(
+            vtt,
+            "{} --> {}",
+            format_timestamp(cue.start, '.'),
+            format_timestamp(cue.end, '.')
+        )
+        .unwrap();
+        if let Some(agent) = cue.line.agent.as_ref() {
+            write!(vtt, "<v {}>", html_escape::encode_text(agent)).unwrap();
+        }
+        if cue.line.words.is_empty() {
+            vtt.push_str(&html_escape::encode_text(&cue.line.text));
+        }
+        // Words starting with the cue need no timestamp, and there is no way
+        // to mark where a word ends.
+        for word in cue.line.words.iter().filter(|word| !word.text.is_empty()) {
+            if word.time > cue.start {
+                write!(vtt, "<{}>", format_timestamp(word.time, '.')).unwrap();
+            }
+            vtt.push_str(&html_escape::encode_text(&word.text));
+        }
+        writeln!(vtt).unwrap();
+        if let Some(translation) = cue.line.translation.as_ref() {
+            writeln!(vtt, "{}", html_escape::encode_text(translation)).unwrap();
+        }
+        writeln!(vtt).unwrap();
+    }
+    vtt
+}
+
+fn to_ass(cues: &[Cue], title: Option<&str>) -> String {
+    let mut ass = String::from("[Script Info]\n");
+    if let Some(title) = title {
+        writeln!(ass, "Title: {}", title).unwrap();
+    }
+    ass.push_str("ScriptType: v4.00+\nPlayResX: 1920\nPlayResY: 1080\n\n");
+    ass.push_str(ASS_HEADER);
+    for cue in cues.iter() {
+        let mut text = karaoke_text(cue);
+        if let Some(translation) = cue.line.translation.as_ref() {
+            write!(text, "\\N{}", escape_ass(translation)).unwrap();
+        }
+        writeln!(
+            ass,
+            "Dialogue: 0,{},{},Default,{},0,0,0,,{}",
+            format_ass_timestamp(cue.start),
+            format_ass_timestamp(cue.end),
+            cue.line
+                .agent
+                .as_deref()
+                .unwrap_or_default()
+                .replace(',', " "),
+            text
+        )
+        .unwrap();
+    }
+    ass
+}
+
+/// The text of `cue` with a `\k` tag before each word, which lasts until the
+/// next word starts or the cue ends.
+fn karaoke_text(cue: &Cue) -> String {
+    let words = &cue.line.words;
+    if words.is_empty() {
+        return escape_ass(&cue.line.text);
+    }
+    // Round the boundaries rather than the durations so that errors don't
+    // add up.
+    let centiseconds = |time: Duration| (time.saturating_sub(cue.start).as_millis() + 5) / 10;
+    let mut text = String::new();
+    if words[0].time > cue.start {
+        write!(text, "{{\\k{}}}", centiseconds(words[0].time)).unwrap();
+    }
+    for (i, word) in words.iter().enumerate() {
+        let next = words.get(i + 1).map(|next| next.time).unwrap_or(cue.end);
+        let duration = centiseconds(next).saturating_sub(centiseconds(word.time));
+        write!(text, "{{\\k{}}}{}", duration, escape_ass(&word.text)).unwrap();
+    }
+    text
+}
+
+/// Keeps braces from being read as override blocks.
+fn escape_ass(text: &str) -> String {
+    text.replace('{', "(").replace('}', ")")
+}
+
+/// Formats `62:03.45`, in centiseconds.
+pub fn format_lrc_timestamp(time: Duration) -> String {
+    let centiseconds = time.as_millis() / 10;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+/// Formats `01:02:03,456` for SRT or `01:02:03.456` for WebVTT.
+fn format_timestamp(time: Duration, separator: char) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+/// Formats `1:02:03.45`, in centiseconds.
+fn format_ass_timestamp(time: Duration) -> String {
+    let centiseconds = time.as_millis() / 10;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centiseconds / 360_000,
+        centiseconds / 6000 % 60,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::format;
+    use crate::lyrics::Word;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn lyrics() -> Lyrics {
+        let mut first = Line::with_words(
+            millis(1000),
+            vec![
+                Word::new(millis(1000), "Hello "),
+                Word::new(millis(1500), "world"),
+            ],
+        );
+        first.end = Some(millis(3000));
+        first.translation = Some("你好，世界".to_string());
+        Lyrics::new(vec![first, Line::new(millis(5000), "Second line")])
+    }
+
+    /// The times, ends, texts and translations of the lines with text.
+    fn summary(lyrics: &Lyrics) -> Vec<(Duration, Option<Duration>, &str, Option<&str>)> {
+        lyrics
+            .lines()
+            .iter()
+            .filter(|line| !line.text.is_empty())
+            .map(|line| {
+                (
+                    line.time,
+                    line.end,
+                    line.text.as_str(),
+                    line.translation.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    fn words(lyrics: &Lyrics) -> Vec<(Duration, &str)> {
+        lyrics.lines()[0]
+            .words
+            .iter()
+            .filter(|word| !word.text.is_empty())
+            .map(|word| (word.time, word.text.as_str()))
+            .collect()
+    }
+
+    fn round_trip(format: Format) -> Lyrics {
+        let exported = export(&lyrics(), format, 0).unwrap();
+        let parsed = format::parse(&exported);
+        assert!(parsed.diagnostics.is_empty(), "{}", exported);
+        parsed.lyrics
+    }
+
+    #[test]
+    fn round_trips_subtitles() {
+        let expected = vec![
+            (
+                millis(1000),
+                Some(millis(3000)),
+                "Hello world",
+                Some("你好，世界"),
+            ),
+            (millis(5000), Some(millis(10000)), "Second line", None),
+        ];
+        assert_eq!(summary(&round_trip(Format::Srt)), expected);
+        for format in [Format::WebVtt, Format::Ass].iter() {
+            let lyrics = round_trip(*format);
+            assert_eq!(summary(&lyrics), expected);
+            assert_eq!(
+                words(&lyrics),
+                [(millis(1000), "Hello "), (millis(1500), "world")]
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_lrc() {
+        let lyrics = round_trip(Format::Lrc);
+        assert_eq!(
+            summary(&lyrics),
+            [
+                (millis(1000), None, "Hello world", Some("你好，世界")),
+                (millis(5000), None, "Second line", None),
+            ]
+        );
+        assert_eq!(
+            words(&lyrics),
+            [(millis(1000), "Hello "), (millis(1500), "world")]
+        );
+        // The end comes back as an empty line.
+        let times: Vec<_> = lyrics.lines().iter().map(|line| line.time).collect();
+        assert_eq!(times, [millis(1000), millis(3000), millis(5000)]);
+    }
+
+    #[test]
+    fn rounds_karaoke_boundaries_rather_than_durations() {
+        let mut line = Line::with_words(
+            millis(1000),
+            vec![
+                Word::new(millis(1000), "a"),
+                Word::new(millis(1334), "b"),
+                Word::new(millis(1667), "c"),
+            ],
+        );
+        line.end = Some(millis(2000));
+        let ass = export(&Lyrics::new(vec![line]), Format::Ass, 0).unwrap();
+        assert!(ass.contains(",{\\k33}a{\\k34}b{\\k33}c\n"), "{}", ass);
+    }
+
+    #[test]
+    fn ends_lines_without_an_end_at_the_next_line() {
+        let lyrics = Lyrics::new(vec![
+            Line::new(millis(1000), "First"),
+            Line::new(millis(4000), "Second"),
+        ]);
+        let srt = export(&lyrics, Format::Srt, 0).unwrap();
+        assert!(
+            srt.contains("00:00:01,000 --> 00:00:04,000\nFirst\n"),
+            "{}",
+            srt
+        );
+    }
+
+    #[test]
+    fn inverts_the_offset() {
+        let lyrics = format::parse("[offset:500]\n[00:01.00]Hello\n").lyrics;
+        let lrc = export(&lyrics, Format::Lrc, 0).unwrap();
+        assert!(lrc.contains("[00:00.50]Hello\n"), "{}", lrc);
+        assert!(!lrc.contains("offset"), "{}", lrc);
+        let srt = export(&lyrics, Format::Srt, 250).unwrap();
+        assert!(srt.starts_with("1\n00:00:00,250 --> "), "{}", srt);
+        let lyrics = format::parse("[offset:-500]\n[00:01.00]Hello\n").lyrics;
+        let lrc = export(&lyrics, Format::Lrc, 0).unwrap();
+        assert!(lrc.contains("[00:01.50]Hello\n"), "{}", lrc);
+    }
+}
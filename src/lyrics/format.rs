@@ -0,0 +1,137 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::lrc;
+use super::plain;
+use super::subtitle;
+use super::ttml;
+use super::Line;
+use super::Lyrics;
+
+/// A problem in one line of a document that was skipped or worked around.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+pub struct Parsed {
+    pub lyrics: Lyrics,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The times of the lines that start before the line above them in the
+    /// document. The lines of [`Parsed::lyrics`] are sorted all the same.
+    pub out_of_order: Vec<Duration>,
+}
+
+/// The formats of lyrics documents that we can read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Lrc,
+    Ttml,
+    Srt,
+    WebVtt,
+    Ass,
+    /// Lyrics without times, one line per line.
+    Plain,
+}
+
+impl Format {
+    /// Guesses the format of `content` from the way it starts. Documents
+    /// without any time tags are taken as plain text.
+    pub fn detect(content: &str) -> Self {
+        let content = content.trim_start_matches('\u{feff}').trim_start();
+        let mut lines = content.lines().map(|line| line.trim());
+        let first = lines.next().unwrap_or_default();
+        if content.starts_with('<') {
+            Format::Ttml
+        } else if first.starts_with("WEBVTT") {
+            Format::WebVtt
+        } else if first.eq_ignore_ascii_case("[script info]")
+            || first.eq_ignore_ascii_case("[events]")
+        {
+            Format::Ass
+        } else if first.parse::<u32>().is_ok()
+            && lines
+                .next()
+                .map(|line| line.contains("-->"))
+                .unwrap_or(false)
+        {
+            Format::Srt
+        } else if lrc::has_time_tags(content) {
+            Format::Lrc
+        } else {
+            Format::Plain
+        }
+    }
+
+    /// The format of files named `*.extension`, e.g. `srt`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "lrc" => Some(Format::Lrc),
+            "ttml" | "xml" => Some(Format::Ttml),
+            "srt" => Some(Format::Srt),
+            "vtt" => Some(Format::WebVtt),
+            "ass" | "ssa" => Some(Format::Ass),
+            "txt" => Some(Format::Plain),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, content: &str) -> Parsed {
+        match self {
+            Format::Lrc => lrc::parse(content),
+            Format::Ttml => ttml::parse(content),
+            Format::Srt => subtitle::parse_srt(content),
+            Format::WebVtt => subtitle::parse_vtt(content),
+            Format::Ass => subtitle::parse_ass(content),
+            Format::Plain => plain::parse(content),
+        }
+    }
+}
+
+/// Turns the second of two lines sharing the same time into the translation
+/// of the first, which is how most bilingual documents are laid out.
+pub fn merge_translations(mut lines: Vec<Line>) -> Vec<Line> {
+    // Keep lines sharing the same time in document order.
+    lines.sort_by_key(|line| line.time);
+    let mut merged: Vec<Line> = vec![];
+    for line in lines {
+        match merged.last_mut() {
+            Some(last) if last.time == line.time && last.translation.is_none() => {
+                if last.text.is_empty() {
+                    *last = line;
+                } else if !line.text.is_empty() && line.text != last.text {
+                    last.translation = Some(line.text);
+                }
+            }
+            _ => merged.push(line),
+        }
+    }
+    merged
+}
+
+/// The times in `times`, in document order, that come before the time above
+/// them.
+pub fn out_of_order(times: impl IntoIterator<Item = Duration>) -> Vec<Duration> {
+    let mut previous = Duration::ZERO;
+    let mut out_of_order = vec![];
+    for time in times {
+        if time < previous {
+            out_of_order.push(time);
+        }
+        previous = time;
+    }
+    out_of_order
+}
+
+/// Parses `content` in whichever format it is in.
+pub fn parse(content: &str) -> Parsed {
+    Format::detect(content).parse(content)
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
+use reqwest::Certificate;
+use reqwest::Client;
+use reqwest::Proxy;
+
+use crate::config::HttpConfig;
+
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+/// Builds the HTTP client shared by the providers.
+pub fn build_client(config: &HttpConfig) -> Result<Client> {
+    let mut builder = Client::builder().timeout(Duration::from_secs(config.timeout));
+    if let Some(proxy) = config.proxy.as_ref() {
+        builder = builder.proxy(Proxy::all(proxy).context("Invalid proxy")?);
+    }
+    if let Some(path) = config.ca_bundle.as_ref() {
+        let bundle = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        // A bundle may contain many certificates while `Certificate::from_pem`
+        // only takes one.
+        for pem in bundle.split_inclusive(PEM_END) {
+            if !pem.contains(PEM_END) {
+                continue;
+            }
+            let certificate = Certificate::from_pem(pem.as_bytes())
+                .with_context(|| format!("Invalid certificate in {}", path.display()))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    if let Some(user_agent) = config.user_agent.as_ref() {
+        builder = builder.user_agent(user_agent);
+    }
+    let mut headers = HeaderMap::new();
+    for (name, value) in config.headers.iter() {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name {}", name))?;
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value of header {}", name))?;
+        headers.insert(name, value);
+    }
+    Ok(builder.default_headers(headers).build()?)
+}
@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::format::merge_translations;
+use super::format::out_of_order;
+use super::format::Diagnostic;
+use super::format::Parsed;
+use super::Line;
+use super::Lyrics;
+use super::Metadata;
+use super::Word;
+
+/// A time tag: `[m:ss]`, `[mm:ss.xx]`, `[mmm:ss.xxx]`, `[mm:ss:xx]` and so on.
+static TIME_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*\[\s*(\d+)\s*:\s*(\d+)\s*(?:[.:]\s*(\d+))?\s*\]").unwrap());
+
+/// A word time tag of enhanced LRC, e.g. `<00:12.34>`.
+static WORD_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<\s*(\d+)\s*:\s*(\d+)\s*(?:[.:]\s*(\d+))?\s*>").unwrap());
+
+/// Any other tag at the start of a line, e.g. `[ti:Title]`.
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*\[([^\]]*)\]").unwrap());
+
+/// The value of a `[length:]` tag, e.g. `03:45` or `3:45.20`.
+static LENGTH: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d+):(\d+)(?:[.:](\d+))?$").unwrap());
+
+/// Parses an LRC document, skipping the lines it can't make sense of instead
+/// of rejecting the whole document.
+///
+/// Lines with several time tags (`[00:12.00][01:30.00]chorus`) are repeated
+/// at each of the times. Word time tags of enhanced LRC
+/// (`[00:12.00]<00:12.00>Hello <00:12.50>world<00:13.00>`) end up in
+/// [`Line::words`], and the second of two lines at the same time ends up in
+/// [`Line::translation`]. ID tags such as `[ti:]` and `[offset:]` end up in
+/// [`Lyrics::metadata`].
+pub fn parse(content: &str) -> Parsed {
+    let mut lines = vec![];
+    let mut metadata = Metadata::default();
+    let mut diagnostics = vec![];
+    // The first time of each line, since repeated lines jump back and forth.
+    let mut starts = vec![];
+    for (index, source) in content.lines().enumerate() {
+        let mut diagnose = |message: String| {
+            diagnostics.push(Diagnostic {
+                line: index + 1,
+                message,
+            })
+        };
+        let mut rest = source.trim_start_matches('\u{feff}');
+        let mut times = vec![];
+        let mut tagged = false;
+        while let Some(captures) = TIME_TAG.captures(rest) {
+            tagged = true;
+            match parse_time(
+                &captures[1],
+                &captures[2],
+                captures.get(3).map(|m| m.as_str()),
+            ) {
+                Some(time) => times.push(time),
+                None => diagnose(format!("invalid time tag {}", captures[0].trim())),
+            }
+            rest = &rest[captures[0].len()..];
+        }
+        if times.is_empty() {
+            if tagged || rest.trim().is_empty() {
+                continue;
+            }
+            if let Some(captures) = TAG.captures(rest) {
+                // Metadata tags are expected; anything else after them is not.
+                let tag = &captures[1];
+                if let Some(colon) = tag.find(':') {
+                    if rest[captures[0].len()..].trim().is_empty() {
+                        let key = tag[..colon].trim().to_lowercase();
+                        let value = tag[colon + 1..].trim();
+                        if let Err(message) = parse_id_tag(&mut metadata, &key, value) {
+                            diagnose(message);
+                        }
+                        continue;
+                    }
+                }
+            }
+            diagnose("no time tag".to_string());
+            continue;
+        }
+        // Word times are absolute, which only fits the first time the line is sung.
+        let first = *times.iter().min().unwrap();
+        starts.push(first);
+        let words = parse_words(rest, first, &mut diagnose);
+        for &time in times.iter() {
+            let line = match words.as_ref() {
+                Some(words) => Line::with_words(time, move_words(words, first, time)),
+                None => Line::new(time, rest.trim()),
+            };
+            lines.push(line);
+        }
+    }
+    Parsed {
+        lyrics: Lyrics::new(merge_translations(lines)).with_metadata(metadata),
+        diagnostics,
+        out_of_order: out_of_order(starts),
+    }
+}
+
+/// Whether `content` has any time tags, which tells LRC apart from plain
+/// text.
+pub fn has_time_tags(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| TIME_TAG.is_match(line.trim_start_matches('\u{feff}')))
+}
+
+/// Splits `text` at its word time tags, if it has any.
+///
+/// Text before the first tag is sung at `line_time`.
+fn parse_words(
+    text: &str,
+    line_time: Duration,
+    diagnose: &mut impl FnMut(String),
+) -> Option<Vec<Word>> {
+    let mut words: Vec<Word> = vec![];
+    let mut start = 0;
+    let mut tagged = false;
+    for captures in WORD_TAG.captures_iter(text) {
+        let tag = captures.get(0).unwrap();
+        let before = &text[start..tag.start()];
+        match words.last_mut() {
+            Some(word) => word.text.push_str(before),
+            None if !before.trim().is_empty() => words.push(Word::new(line_time, before)),
+            None => {}
+        }
+        start = tag.end();
+        tagged = true;
+        match parse_time(
+            &captures[1],
+            &captures[2],
+            captures.get(3).map(|m| m.as_str()),
+        ) {
+            Some(time) => words.push(Word::new(time, "")),
+            // The text goes on with the previous word.
+            None => diagnose(format!("invalid word time tag {}", tag.as_str())),
+        }
+    }
+    if !tagged {
+        return None;
+    }
+    match words.last_mut() {
+        Some(word) => word.text.push_str(&text[start..]),
+        None => words.push(Word::new(line_time, &text[start..])),
+    }
+    Some(words)
+}
+
+/// Moves `words` of a line at `from` to a repetition of it at `to`.
+fn move_words(words: &[Word], from: Duration, to: Duration) -> Vec<Word> {
+    words
+        .iter()
+        .map(|word| {
+            let time = if to >= from {
+                word.time + (to - from)
+            } else {
+                word.time.checked_sub(from - to).unwrap_or_default()
+            };
+            Word::new(time, &word.text)
+        })
+        .collect()
+}
+
+fn parse_id_tag(metadata: &mut Metadata, key: &str, value: &str) -> Result<(), String> {
+    let text = || Some(value.to_string()).filter(|value| !value.is_empty());
+    match key {
+        "ti" => metadata.title = text(),
+        "ar" => metadata.artist = text(),
+        "al" => metadata.album = text(),
+        "by" => metadata.by = text(),
+        "length" => {
+            let captures = LENGTH
+                .captures(value)
+                .ok_or_else(|| format!("invalid length {}", value))?;
+            let length = parse_time(
+                &captures[1],
+                &captures[2],
+                captures.get(3).map(|m| m.as_str()),
+            );
+            metadata.length = Some(length.ok_or_else(|| format!("invalid length {}", value))?);
+        }
+        "offset" => {
+            metadata.offset = value
+                .trim_start_matches('+')
+                .parse()
+                .map_err(|_| format!("invalid offset {}", value))?;
+        }
+        // Other tags, e.g. [au:] and [re:], don't matter to us.
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_time(minutes: &str, seconds: &str, fraction: Option<&str>) -> Option<Duration> {
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    if seconds >= 60 {
+        return None;
+    }
+    // `.x` is tenths, `.xx` hundredths and `.xxx` milliseconds of a second.
+    let millis = match fraction {
+        Some(fraction) => {
+            let digits = &fraction[..fraction.len().min(3)];
+            let value: u64 = digits.parse().ok()?;
+            value * 10u64.pow(3 - digits.len() as u32)
+        }
+        None => 0,
+    };
+    Some(Duration::from_millis(
+        (minutes * 60 + seconds) * 1000 + millis,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(parsed: &Parsed) -> Vec<(Duration, &str)> {
+        parsed
+            .lyrics
+            .lines()
+            .iter()
+            .map(|line| (line.time, line.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_time_tags_of_any_width() {
+        let parsed = parse("[1:02]a\n[01:03:50]b\n[01:04]c\n[01:06.123]d\n[100:05.1]e\n");
+        assert_eq!(
+            times(&parsed),
+            [
+                (Duration::from_secs(62), "a"),
+                (Duration::from_millis(63_500), "b"),
+                (Duration::from_secs(64), "c"),
+                (Duration::from_millis(66_123), "d"),
+                (Duration::from_millis(6_005_100), "e"),
+            ]
+        );
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn repeats_lines_at_each_of_their_times() {
+        let parsed = parse("[00:12.00][01:30.00]<00:12.00>Hello <00:12.50>world\n[00:20.00]b\n");
+        let lines = parsed.lyrics.lines();
+        assert_eq!(
+            times(&parsed),
+            [
+                (Duration::from_secs(12), "Hello world"),
+                (Duration::from_secs(20), "b"),
+                (Duration::from_secs(90), "Hello world"),
+            ]
+        );
+        let word_times = lines[2]
+            .words
+            .iter()
+            .map(|word| word.time)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            word_times,
+            [Duration::from_secs(90), Duration::from_millis(90_500)]
+        );
+        // Only the first time of a line counts for its order.
+        assert!(parsed.out_of_order.is_empty());
+    }
+
+    #[test]
+    fn takes_the_second_line_at_a_time_as_the_translation() {
+        let parsed = parse("[00:01.00]Hello\n[00:01.00]你好\n");
+        let lines = parsed.lyrics.lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].translation.as_deref(), Some("你好"));
+    }
+
+    #[test]
+    fn reads_id_tags() {
+        let parsed =
+            parse("[ti:Song]\n[ar:Artist]\n[length:3:45.20]\n[offset:+250]\n[00:01.00]a\n");
+        let metadata = parsed.lyrics.metadata();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.length, Some(Duration::from_millis(225_200)));
+        assert_eq!(metadata.offset, 250);
+    }
+
+    #[test]
+    fn reports_the_lines_it_skips() {
+        let parsed = parse(
+            "[00:01.00]a\n[00:70.00]b\nno tag\n[offset:soon]\n[00:02.00]<00:02.00>c <00:99.00>d\n",
+        );
+        assert_eq!(
+            parsed
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "line 2: invalid time tag [00:70.00]",
+                "line 3: no time tag",
+                "line 4: invalid offset soon",
+                "line 5: invalid word time tag <00:99.00>",
+            ]
+        );
+        assert_eq!(
+            times(&parsed),
+            [
+                (Duration::from_secs(1), "a"),
+                (Duration::from_secs(2), "c d"),
+            ]
+        );
+    }
+}
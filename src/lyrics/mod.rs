@@ -1,3 +1,49 @@
+mod cache;
+mod chinese;
+mod corrections;
+mod error;
+mod export;
+pub mod format;
+mod http;
+pub mod lrc;
+mod normalize;
+mod offsets;
+pub mod plain;
+mod provider;
 mod query;
+mod retime;
+mod romanize;
+mod score;
+pub mod subtitle;
+pub mod tags;
+mod tap_sync;
+mod timeline;
+mod track;
+pub mod ttml;
+mod validate;
 
+pub use cache::CacheEntry;
+pub use cache::LyricsCache;
+pub use chinese::ChineseConverter;
+pub use corrections::CorrectionStore;
+pub use error::LyricsError;
+pub use export::export;
+pub use normalize::query_variants;
+pub use normalize::QueryVariant;
+pub use offsets::OffsetStore;
+pub use provider::save_to_folder;
+pub use provider::Candidate;
+pub use provider::FetchedLyrics;
+pub use provider::ProviderChain;
 pub use query::Query;
+pub use retime::retime;
+pub use retime::Anchor;
+pub use romanize::romanize;
+pub use tap_sync::TapSync;
+pub use timeline::shift;
+pub use timeline::Line;
+pub use timeline::Lyrics;
+pub use timeline::Metadata;
+pub use timeline::Progress;
+pub use timeline::Word;
+pub use track::Track;
@@ -0,0 +1,126 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::Track;
+
+static FEATURING: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\s*[(\[]\s*(feat\.?|ft\.?|featuring|with)\s[^)\]]*[)\]]|\s+(feat\.?|ft\.?|featuring)\s.*$",
+    )
+    .unwrap()
+});
+
+static VERSION_SUFFIX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\s+-\s+[^-]*\b(remaster(ed)?|live|edit|version|mix|mono|stereo|demo|acoustic|instrumental|bonus track)\b[^-]*$",
+    )
+    .unwrap()
+});
+
+static VERSION_BRACKETED: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\s*[(\[][^)\]]*\b(remaster(ed)?|live|edit|version|mix|mono|stereo|demo|acoustic|instrumental|bonus track)\b[^)\]]*[)\]]",
+    )
+    .unwrap()
+});
+
+static BRACKETED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\s*[(\[{【「『〈《<][^)\]}】」』〉》>]*[)\]}】」』〉》>]").unwrap());
+
+static ARTIST_SEPARATOR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\s*(,|&|、|/|;|\s(feat\.?|ft\.?|featuring|vs\.?)\s)\s*").unwrap()
+});
+
+/// The title and artist actually sent to the providers for a track.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryVariant {
+    pub name: String,
+    pub artist: String,
+}
+
+impl QueryVariant {
+    fn new(name: &str, artist: &str) -> Self {
+        Self {
+            name: name.trim().to_string(),
+            artist: artist.trim().to_string(),
+        }
+    }
+}
+
+/// Replaces full-width ASCII variants and ideographic spaces with their
+/// half-width counterparts, and collapses whitespace.
+pub fn to_half_width(s: &str) -> String {
+    let s = s
+        .chars()
+        .map(|c| match c {
+            '\u{ff01}'..='\u{ff5e}' => std::char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            '\u{2018}' | '\u{2019}' => '\'',
+            '\u{201c}' | '\u{201d}' => '"',
+            _ => c,
+        })
+        .collect::<String>();
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Strips featured artists and remaster/live/edit qualifiers from a title.
+pub fn clean_title(title: &str) -> String {
+    let title = to_half_width(title);
+    let title = FEATURING.replace_all(&title, "");
+    let title = VERSION_SUFFIX.replace(&title, "");
+    let title = VERSION_BRACKETED.replace_all(&title, "");
+    title.trim().to_string()
+}
+
+/// Strips every bracketed part of a title.
+pub fn bare_title(title: &str) -> String {
+    let title = clean_title(title);
+    let bare = BRACKETED.replace_all(&title, "");
+    // Titles made of a bracketed part only are better left alone.
+    if bare.trim().is_empty() {
+        title
+    } else {
+        bare.trim().to_string()
+    }
+}
+
+/// The first of several credited artists, e.g. `A` for `A & B, C`.
+pub fn primary_artist(artist: &str) -> String {
+    let artist = to_half_width(artist);
+    ARTIST_SEPARATOR
+        .split(&artist)
+        .find(|s| !s.trim().is_empty())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// Returns the queries to try for `track`, from the most to the least
+/// specific, without duplicates.
+pub fn query_variants(track: &Track) -> Vec<QueryVariant> {
+    let artist = to_half_width(&track.artist);
+    let primary_artist = primary_artist(&track.artist);
+    let album_artist = to_half_width(&track.album_artist);
+    let clean_title = clean_title(&track.name);
+    let bare_title = bare_title(&track.name);
+    let candidates = [
+        QueryVariant::new(&track.name, &track.artist),
+        QueryVariant::new(&clean_title, &artist),
+        QueryVariant::new(&clean_title, &primary_artist),
+        QueryVariant::new(&bare_title, &primary_artist),
+        QueryVariant::new(&clean_title, &album_artist),
+        QueryVariant::new(&bare_title, &album_artist),
+    ];
+    let mut variants: Vec<QueryVariant> = vec![];
+    for candidate in candidates.iter() {
+        if candidate.name.is_empty() || candidate.artist.is_empty() {
+            continue;
+        }
+        if !variants.contains(candidate) {
+            variants.push(candidate.clone());
+        }
+    }
+    variants
+}
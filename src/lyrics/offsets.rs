@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::warn;
+
+/// Timing corrections made by the user, in milliseconds per track key (see
+/// [`Track::key`](super::Track::key)).
+///
+/// Positive values make lines show up earlier, like the `[offset:]` tag.
+pub struct OffsetStore {
+    path: PathBuf,
+    offsets: HashMap<String, i64>,
+}
+
+impl OffsetStore {
+    pub fn load(path: PathBuf) -> Self {
+        let offsets = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| {
+                serde_json::from_str(&content)
+                    .map_err(|e| warn!("Corrupted {}: {:?}", path.display(), e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        Self { path, offsets }
+    }
+
+    pub fn get(&self, key: &str) -> i64 {
+        self.offsets.get(key).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, key: &str, offset: i64) -> Result<()> {
+        if offset == 0 {
+            self.offsets.remove(key);
+        } else {
+            self.offsets.insert(key.to_string(), offset);
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.offsets)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::format::Diagnostic;
+use super::format::Format;
+use super::format::Parsed;
+use super::romanize::is_han;
+use super::romanize::is_hangul;
+use super::romanize::is_kana;
+use super::Line;
+use super::Lyrics;
+use super::Metadata;
+
+/// How long a syllable lasts at a typical pace of singing, for when the
+/// length of the track is not known.
+const SYLLABLE_DURATION: Duration = Duration::from_millis(300);
+
+/// How many syllables a break between stanzas is worth.
+const STANZA_BREAK_SYLLABLES: u32 = 8;
+
+/// The shares of the track taken by the intro, before the first line, and by
+/// the outro, after the last.
+const INTRO_SHARE: f64 = 0.08;
+const OUTRO_SHARE: f64 = 0.08;
+
+/// The start of an HTML document, e.g. `<!DOCTYPE html>` or `<html lang="en">`.
+static HTML_ROOT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)<(?:!doctype\s+html|html)[\s>]").unwrap());
+
+/// A tag of the HTML that web pages are made of, e.g. `<!DOCTYPE html>`,
+/// `<div class="error">` or `</body>`.
+static HTML_TAG: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?i)<(?:!doctype|/?(?:html|head|body|meta|title|script|style|div|span|form|a|p|br))",
+        r"(?:\s[^>]*)?/?>"
+    ))
+    .unwrap()
+});
+
+const VOWELS: &str = "aeiouyàáâãäåæèéêëìíîïòóôõöøùúûüýÿ";
+
+/// Parses lyrics without times: one line per line of text, with blank lines
+/// or headings like `[Chorus]` between stanzas.
+///
+/// The lines are spread at a typical pace of singing and marked as
+/// [`Metadata::estimated`]; [`estimate`] spreads them over the track once its
+/// length is known. Web pages give no lines, see [`is_web_page`].
+pub fn parse(content: &str) -> Parsed {
+    if is_web_page(content) {
+        return Parsed {
+            lyrics: Lyrics::default(),
+            diagnostics: vec![Diagnostic {
+                line: 1,
+                message: "a web page, not lyrics".to_string(),
+            }],
+            out_of_order: vec![],
+        };
+    }
+    let mut lines: Vec<Line> = vec![];
+    for source in content.lines() {
+        let text = source.trim_start_matches('\u{feff}').trim();
+        let is_heading = text.starts_with('[') && text.ends_with(']');
+        if text.is_empty() || is_heading {
+            // An empty line marks a stanza break.
+            if lines
+                .last()
+                .map(|line| !line.text.is_empty())
+                .unwrap_or(false)
+            {
+                lines.push(Line::default());
+            }
+        } else {
+            lines.push(Line::new(Duration::ZERO, text));
+        }
+    }
+    while lines
+        .last()
+        .map(|line| line.text.is_empty())
+        .unwrap_or(false)
+    {
+        lines.pop();
+    }
+    let lyrics =
+        Lyrics::new(spread(lines, Duration::ZERO, SYLLABLE_DURATION)).with_metadata(Metadata {
+            estimated: true,
+            ..Default::default()
+        });
+    Parsed {
+        lyrics,
+        diagnostics: vec![],
+        out_of_order: vec![],
+    }
+}
+
+/// Whether `content` is a web page rather than lyrics, such as the error page
+/// of a proxy or the login page of a captive portal, served as if it were
+/// found. Pages starting with `<` would otherwise be taken as TTML, which has
+/// `<p>` and `<span>` tags too.
+pub fn is_web_page(content: &str) -> bool {
+    HTML_ROOT.is_match(content)
+        || (Format::detect(content) == Format::Plain && HTML_TAG.is_match(content))
+}
+
+/// Spreads estimated lyrics over a track of `duration`, leaving room for the
+/// intro and the outro. Each line takes a share of the time by its number of
+/// syllables. Lyrics with real times are returned as they are.
+pub fn estimate(lyrics: Lyrics, duration: Duration) -> Lyrics {
+    if !lyrics.metadata().estimated {
+        return lyrics;
+    }
+    let metadata = lyrics.metadata().clone();
+    // Without the empty line that ends the last one.
+    let mut lines = lyrics.lines().to_vec();
+    if lines
+        .last()
+        .map(|line| line.text.is_empty())
+        .unwrap_or(false)
+    {
+        lines.pop();
+    }
+    let total = lines.iter().map(weight).sum::<u32>().max(1);
+    let sung = duration.mul_f64(1. - INTRO_SHARE - OUTRO_SHARE);
+    let lines = spread(lines, duration.mul_f64(INTRO_SHARE), sung / total);
+    Lyrics::new(lines).with_metadata(metadata)
+}
+
+/// Times `lines` one after another from `start`, giving each `unit` per
+/// syllable, and ends the last one with an empty line.
+fn spread(lines: Vec<Line>, start: Duration, unit: Duration) -> Vec<Line> {
+    let mut time = start;
+    let mut spread = vec![];
+    for mut line in lines {
+        line.time = time;
+        line.end = None;
+        time += unit * weight(&line);
+        spread.push(line);
+    }
+    if !spread.is_empty() {
+        spread.push(Line::new(time, ""));
+    }
+    spread
+}
+
+fn weight(line: &Line) -> u32 {
+    if line.text.is_empty() {
+        STANZA_BREAK_SYLLABLES
+    } else {
+        syllables(&line.text).max(1)
+    }
+}
+
+/// Roughly how many syllables `text` has: one for each Chinese, Japanese or
+/// Korean character, and one for each group of vowels in other words.
+fn syllables(text: &str) -> u32 {
+    let mut count = 0;
+    for word in text.split_whitespace() {
+        let mut vowel_groups = 0;
+        let mut has_letters = false;
+        let mut in_vowels = false;
+        for c in word.chars() {
+            if is_han(c) || is_kana(c) || is_hangul(c) {
+                count += 1;
+                in_vowels = false;
+            } else if c.is_alphanumeric() {
+                has_letters = true;
+                let is_vowel = c.to_lowercase().any(|c| VOWELS.contains(c));
+                if is_vowel && !in_vowels {
+                    vowel_groups += 1;
+                }
+                in_vowels = is_vowel;
+            } else {
+                in_vowels = false;
+            }
+        }
+        if has_letters {
+            count += vowel_groups.max(1);
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_web_pages() {
+        let page = "Access denied\n<!DOCTYPE html>\n<html><body><p>Log in</p></body></html>\n";
+        let parsed = parse(page);
+        assert!(parsed.lyrics.is_empty());
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert!(!is_web_page("I <3 you\n<Chorus>\n"));
+        assert!(is_web_page(
+            "<!DOCTYPE html>\n<html>\n<head><title>Sign in</title></head>\n</html>\n"
+        ));
+        assert!(!is_web_page(
+            "<tt><body><div><p begin=\"1s\"><span>Hello</span></p></div></body></tt>"
+        ));
+    }
+
+    fn times(lyrics: &Lyrics) -> Vec<(u128, &str)> {
+        lyrics
+            .lines()
+            .iter()
+            .map(|line| (line.time.as_millis(), line.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn breaks_stanzas_at_blank_lines_and_headings() {
+        let lyrics = parse("[Verse]\nHello world\n\n\nSecond line\n[Chorus]\n").lyrics;
+        assert!(lyrics.metadata().estimated);
+        assert_eq!(
+            times(&lyrics),
+            [
+                (0, "Hello world"),
+                (900, ""),
+                (3300, "Second line"),
+                (4500, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn spreads_lines_over_the_track_by_syllables() {
+        let lyrics = parse("Hello world\n\nSecond line\n").lyrics;
+        let lyrics = estimate(lyrics, Duration::from_secs(100));
+        assert_eq!(
+            times(&lyrics),
+            [
+                (8000, "Hello world"),
+                (24800, ""),
+                (69600, "Second line"),
+                (92000, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_synced_lyrics_alone() {
+        let lyrics = Lyrics::new(vec![Line::new(Duration::from_secs(5), "a")]);
+        let estimated = estimate(lyrics.clone(), Duration::from_secs(100));
+        assert_eq!(estimated.lines(), lyrics.lines());
+    }
+
+    #[test]
+    fn counts_syllables() {
+        assert_eq!(syllables("Hello world"), 3);
+        assert_eq!(syllables("你好 こんにちは 안녕"), 9);
+        assert_eq!(syllables("rhythm 42 !"), 2);
+    }
+}
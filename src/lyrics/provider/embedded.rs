@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+use log::info;
+use log::warn;
+use tokio::task::spawn_blocking;
+
+use super::Candidate;
+use super::LyricsProvider;
+use crate::lyrics::tags::read_lyrics;
+use crate::lyrics::LyricsError;
+use crate::lyrics::Track;
+
+/// The lyrics embedded in the tags of the audio file being played.
+pub struct EmbeddedLyrics;
+
+#[async_trait]
+impl LyricsProvider for EmbeddedLyrics {
+    fn name(&self) -> &str {
+        "embedded"
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    async fn get_lyrics(&self, track: &Track) -> Result<Vec<Candidate>, LyricsError> {
+        let location = match track.location.clone() {
+            Some(location) => location,
+            None => return Ok(vec![]),
+        };
+        let result = spawn_blocking(move || read_lyrics(&location))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+        match result {
+            Ok(Some(content)) => {
+                info!("Found embedded lyrics");
+                // They belong to the track, whatever they say.
+                Ok(vec![Candidate {
+                    name: Some(track.name.clone()),
+                    artist: Some(track.artist.clone()),
+                    album: Some(track.album.clone()),
+                    ..Candidate::new(content)
+                }])
+            }
+            Ok(None) => Ok(vec![]),
+            Err(e) => {
+                warn!("Failed to read the tags: {:?}", e);
+                Ok(vec![])
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use log::info;
+use log::warn;
+use notify::watcher;
+use notify::DebouncedEvent;
+use notify::RecursiveMode;
+use notify::Watcher;
+
+use super::Candidate;
+use super::LyricsProvider;
+use crate::lyrics::export;
+use crate::lyrics::format;
+use crate::lyrics::format::Format;
+use crate::lyrics::normalize::to_half_width;
+use crate::lyrics::Lyrics;
+use crate::lyrics::LyricsError;
+use crate::lyrics::Track;
+
+/// The extensions of sidecar files, from the most preferred.
+const SIDECAR_EXTENSIONS: [&str; 6] = ["lrc", "ttml", "srt", "vtt", "ass", "txt"];
+
+/// Characters that can't be part of file names on Windows, and so are often
+/// replaced or dropped from titles like `What's Up?`.
+const RESERVED_CHARS: &[char] = &['\\', '/', ':', '*', '?', '"', '<', '>', '|', '_'];
+
+/// Lyrics files on this computer.
+///
+/// A sidecar next to the audio file, e.g. `Song.lrc` for `Song.m4a`, comes
+/// first. Otherwise, files in the lyrics folder are matched by their names,
+/// `Artist - Title.lrc`, or by their `[ar:]` and `[ti:]` tags. The folder is
+/// indexed once and then watched, so that new files are picked up at once.
+pub struct LocalLyrics {
+    index: Arc<RwLock<Index>>,
+}
+
+/// The keys of the lyrics files in the folder, by path.
+#[derive(Default)]
+struct Index {
+    files: HashMap<PathBuf, Vec<String>>,
+}
+
+impl LocalLyrics {
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        let index = Arc::new(RwLock::new(Index::default()));
+        if let Some(directory) = directory {
+            let index = index.clone();
+            // Reading every file of a large folder takes a while, and lookups
+            // make do with the files indexed so far.
+            thread::spawn(move || index_and_watch(directory, index));
+        }
+        Self { index }
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for LocalLyrics {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    async fn get_lyrics(&self, track: &Track) -> Result<Vec<Candidate>, LyricsError> {
+        if let Some(location) = track.location.as_ref() {
+            for extension in SIDECAR_EXTENSIONS.iter() {
+                let path = location.with_extension(extension);
+                if let Some(content) = read_lyrics(&path).await {
+                    info!("Found sidecar {}", path.display());
+                    // It belongs to the track, whatever its tags say.
+                    return Ok(vec![Candidate {
+                        name: Some(track.name.clone()),
+                        artist: Some(track.artist.clone()),
+                        album: Some(track.album.clone()),
+                        ..Candidate::new(content)
+                    }]);
+                }
+            }
+        }
+        let key = index_key(&track.artist, &track.name);
+        let paths = self.index.read().unwrap().find(&key);
+        let mut candidates = vec![];
+        for path in paths {
+            if let Some(content) = read_lyrics(&path).await {
+                info!("Found {}", path.display());
+                candidates.push(Candidate::new(content));
+            }
+        }
+        Ok(candidates)
+    }
+}
+
+impl Index {
+    /// Replaces the files at and below `path` with `files`.
+    fn replace(&mut self, path: &Path, files: HashMap<PathBuf, Vec<String>>) {
+        self.remove(path);
+        self.files.extend(files);
+    }
+
+    /// Forgets the files at and below `path`.
+    fn remove(&mut self, path: &Path) {
+        self.files.retain(|file, _| !file.starts_with(path));
+    }
+
+    fn find(&self, key: &str) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .filter(|(_, keys)| keys.iter().any(|k| k == key))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+/// Indexes the files in `directory`, then keeps `index` up to date with
+/// them. Files are read before taking the lock, so that lookups don't wait
+/// for them.
+fn index_and_watch(directory: PathBuf, index: Arc<RwLock<Index>>) {
+    // Watch first, so that no change made while indexing is missed.
+    let (sender, receiver) = channel();
+    let watcher = watcher(sender, Duration::from_secs(1)).and_then(|mut watcher| {
+        watcher
+            .watch(&directory, RecursiveMode::Recursive)
+            .map(|_| watcher)
+    });
+    let files = read_files(&directory);
+    info!(
+        "Indexed {} lyrics files in {}",
+        files.len(),
+        directory.display()
+    );
+    index.write().unwrap().replace(&directory, files);
+    // Stops watching once dropped.
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("Failed to watch {}: {:?}", directory.display(), e);
+            return;
+        }
+    };
+    for event in receiver {
+        match event {
+            DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+                let files = read_files(&path);
+                index.write().unwrap().replace(&path, files);
+            }
+            DebouncedEvent::Remove(path) => index.write().unwrap().remove(&path),
+            DebouncedEvent::Rename(from, to) => {
+                let files = read_files(&to);
+                let mut index = index.write().unwrap();
+                index.remove(&from);
+                index.replace(&to, files);
+            }
+            DebouncedEvent::Rescan => {
+                let files = read_files(&directory);
+                index.write().unwrap().replace(&directory, files);
+            }
+            DebouncedEvent::Error(e, path) => warn!("Watch error at {:?}: {:?}", path, e),
+            _ => {}
+        }
+    }
+}
+
+/// Reads the keys of the lyrics file at `path`, or of those in the folder at
+/// `path` and its subfolders.
+fn read_files(path: &Path) -> HashMap<PathBuf, Vec<String>> {
+    let mut files = HashMap::new();
+    if path.is_dir() {
+        read_folder(path, &mut files);
+    } else if let Some(keys) = file_keys(path) {
+        files.insert(path.to_path_buf(), keys);
+    }
+    files
+}
+
+/// Reads the keys of the lyrics files in the folder at `path` into `files`.
+/// Links to folders are not followed, since they may lead back up.
+fn read_folder(path: &Path, files: &mut HashMap<PathBuf, Vec<String>>) {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read {}: {:?}", path.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => read_folder(&path, files),
+            _ => {
+                if let Some(keys) = file_keys(&path) {
+                    files.insert(path, keys);
+                }
+            }
+        }
+    }
+}
+
+/// Saves `lyrics` as LRC in the lyrics folder `directory`, named
+/// `Artist - Title.lrc` after `track` so that they are found for it, and
+/// returns the path of the file. A file of that name is never overwritten.
+pub fn save_to_folder(directory: &Path, track: &Track, lyrics: &Lyrics) -> Result<PathBuf> {
+    let name = format!("{} - {}", track.artist, track.name).replace(RESERVED_CHARS, "_");
+    let path = directory.join(format!("{}.lrc", name.trim()));
+    let content = export(lyrics, Format::Lrc, 0)?;
+    fs::create_dir_all(directory)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(content.as_bytes())?;
+    Ok(path)
+}
+
+/// The keys that a lyrics file can be found by: the `Artist - Title` of its
+/// name and of its tags. `None` if it is not a lyrics file.
+fn file_keys(path: &Path) -> Option<Vec<String>> {
+    Format::from_extension(&path.extension()?.to_string_lossy())?;
+    let mut keys = vec![];
+    if let Some((artist, title)) = path.file_stem()?.to_string_lossy().split_once(" - ") {
+        keys.push(index_key(artist, title));
+    }
+    let content = decode(fs::read(path).ok()?);
+    let metadata = format::parse(&content).lyrics.metadata().clone();
+    if let (Some(artist), Some(title)) = (metadata.artist, metadata.title) {
+        let key = index_key(&artist, &title);
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    Some(keys)
+}
+
+/// Normalizes `Artist - Title` so that case, width and characters that file
+/// names can't hold don't matter.
+fn index_key(artist: &str, title: &str) -> String {
+    let normalize = |s: &str| {
+        to_half_width(s)
+            .to_lowercase()
+            .replace(RESERVED_CHARS, " ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    format!("{} - {}", normalize(artist), normalize(title))
+}
+
+async fn read_lyrics(path: &Path) -> Option<String> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    Some(decode(bytes)).filter(|content| !content.trim().is_empty())
+}
+
+fn decode(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LRC: &str = "[00:01.00]Hello world\n";
+
+    fn track(location: Option<PathBuf>) -> Track {
+        Track {
+            name: "Hello".to_string(),
+            artist: "iLyrics".to_string(),
+            album: String::new(),
+            album_artist: String::new(),
+            duration: None,
+            location,
+        }
+    }
+
+    fn index(directory: &Path) -> Index {
+        let mut index = Index::default();
+        index.replace(directory, read_files(directory));
+        index
+    }
+
+    #[test]
+    fn indexes_files_by_name_and_tags() {
+        let directory = tempfile::tempdir().unwrap();
+        let subfolder = directory.path().join("Singles");
+        fs::create_dir(&subfolder).unwrap();
+        let by_name = directory.path().join("iLyrics - Hello.lrc");
+        let by_tags = subfolder.join("01.lrc");
+        fs::write(&by_name, LRC).unwrap();
+        fs::write(&by_tags, format!("[ar:ＩLyrics]\n[ti:hello]\n{}", LRC)).unwrap();
+        fs::write(directory.path().join("iLyrics - Hello.md"), LRC).unwrap();
+        let index = index(directory.path());
+        let mut found = index.find(&index_key("iLyrics", "Hello"));
+        found.sort();
+        assert_eq!(found, [by_tags, by_name]);
+        assert!(index.find(&index_key("iLyrics", "Goodbye")).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_follow_links_to_folders() {
+        let directory = tempfile::tempdir().unwrap();
+        let subfolder = directory.path().join("Singles");
+        fs::create_dir(&subfolder).unwrap();
+        fs::write(subfolder.join("iLyrics - Hello.lrc"), LRC).unwrap();
+        std::os::unix::fs::symlink(directory.path(), subfolder.join("Loop")).unwrap();
+        let index = index(directory.path());
+        assert_eq!(index.find(&index_key("iLyrics", "Hello")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn prefers_sidecars() {
+        let directory = tempfile::tempdir().unwrap();
+        let location = directory.path().join("01 Hello.m4a");
+        fs::write(directory.path().join("01 Hello.txt"), "Hello world").unwrap();
+        fs::write(directory.path().join("01 Hello.lrc"), LRC).unwrap();
+        let local = LocalLyrics::new(None);
+        let candidates = local.get_lyrics(&track(Some(location))).await.unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].content, LRC);
+        assert_eq!(candidates[0].name.as_deref(), Some("Hello"));
+        let candidates = local.get_lyrics(&track(None)).await.unwrap();
+        assert!(candidates.is_empty());
+    }
+}
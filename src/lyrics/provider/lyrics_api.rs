@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+use log::warn;
+use reqwest::Client;
+use reqwest::StatusCode;
+
+use super::Candidate;
+use super::LyricsProvider;
+use crate::lyrics::LyricsError;
+use crate::lyrics::Track;
+
+/// The provider backed by [lyrics-api](https://lyrics-api.lujjjh.com/), or a
+/// compatible server at another endpoint, e.g. a mirror or a local stand-in.
+pub struct LyricsApi {
+    client: Client,
+    endpoint: String,
+}
+
+impl LyricsApi {
+    pub fn new(client: Client, endpoint: &str) -> Self {
+        Self {
+            client,
+            endpoint: endpoint.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for LyricsApi {
+    fn name(&self) -> &str {
+        "lyrics-api"
+    }
+
+    async fn get_lyrics(&self, track: &Track) -> Result<Vec<Candidate>, LyricsError> {
+        let mut query = vec![
+            ("name", track.name.clone()),
+            ("artist", track.artist.clone()),
+        ];
+        // Tell versions of the track apart, e.g. a radio edit from the album
+        // version.
+        if !track.album.is_empty() {
+            query.push(("album", track.album.clone()));
+        }
+        if let Some(duration) = track.duration {
+            query.push(("duration", duration.as_secs().to_string()));
+        }
+        let response = self.client.get(&self.endpoint).query(&query).send().await?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(vec![]);
+        }
+        // Other client errors won't go away by asking again.
+        if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            warn!("Bad status: {}", status);
+            return Ok(vec![]);
+        }
+        let body = response.error_for_status()?.text().await?;
+        Ok(vec![Candidate::new(body)])
+    }
+}
@@ -0,0 +1,192 @@
+mod embedded;
+mod local;
+mod lyrics_api;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::info;
+use log::warn;
+
+use super::format::Format;
+use super::http::build_client;
+use super::plain::is_web_page;
+use super::score::rank;
+use super::score::MIN_SCORE;
+use super::validate::is_for_another_track;
+use super::LyricsError;
+use super::Track;
+use crate::config::Config;
+
+pub use embedded::EmbeddedLyrics;
+pub use local::save_to_folder;
+pub use local::LocalLyrics;
+pub use lyrics_api::LyricsApi;
+
+/// A source of lyrics documents.
+#[async_trait]
+pub trait LyricsProvider: Send + Sync {
+    /// A short identifier of the provider, used in logs and cache entries.
+    fn name(&self) -> &str;
+
+    /// Whether the provider reads files on this computer. These are cheap to
+    /// read and may change at any time, so they are asked before the cache
+    /// and their lyrics are not cached.
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// Looks up the lyrics of `track`.
+    ///
+    /// Returns every document that may belong to the track, or nothing if the
+    /// provider has no lyrics for it.
+    async fn get_lyrics(&self, track: &Track) -> Result<Vec<Candidate>, LyricsError>;
+}
+
+/// A raw lyrics document, with whatever the provider knows about the
+/// recording it was made for.
+#[derive(Debug, Clone, Default)]
+pub struct Candidate {
+    pub content: String,
+    pub name: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+    /// A parallel document with the translation of `content`, if the provider
+    /// ships it separately.
+    pub translation: Option<String>,
+}
+
+impl Candidate {
+    pub fn new(content: String) -> Self {
+        Self {
+            content,
+            ..Default::default()
+        }
+    }
+}
+
+/// A lyrics document together with the provider it came from.
+#[derive(Debug, Clone)]
+pub struct FetchedLyrics {
+    pub provider: String,
+    pub content: String,
+    pub translation: Option<String>,
+    /// Whether no provider matched the track well, and this is only the best
+    /// of the poor matches.
+    pub poor_match: bool,
+}
+
+/// An ordered list of providers that are tried one after another until one of
+/// them has lyrics for the track.
+#[derive(Default)]
+pub struct ProviderChain {
+    providers: Vec<Box<dyn LyricsProvider>>,
+}
+
+impl ProviderChain {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Builds the chain of providers listed in the configuration.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let client = build_client(&config.http)?;
+        let mut chain = Self::new();
+        for name in config.lyrics.providers.iter() {
+            chain = match name.as_str() {
+                "local" => chain.with(LocalLyrics::new(config.lyrics.directory.clone())),
+                "embedded" => chain.with(EmbeddedLyrics),
+                "lyrics-api" => {
+                    chain.with(LyricsApi::new(client.clone(), &config.lyrics_api.endpoint))
+                }
+                _ => {
+                    warn!("Unknown provider: {}", name);
+                    chain
+                }
+            };
+        }
+        Ok(chain)
+    }
+
+    pub fn with(mut self, provider: impl LyricsProvider + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    /// Asks each remote provider in order and returns the best candidate of
+    /// the first one that matches the track well enough, or the best of the
+    /// poor matches if none does. Plain text that matches well is only
+    /// returned if no provider has synced lyrics.
+    ///
+    /// A failing provider does not stop the chain; its error is only returned
+    /// if no other provider has lyrics for the track, so that a transient
+    /// failure is not mistaken for [`LyricsError::NotFound`].
+    pub async fn get_lyrics(&self, track: &Track) -> Result<FetchedLyrics, LyricsError> {
+        self.get_lyrics_from(track, false).await
+    }
+
+    /// Like [`ProviderChain::get_lyrics`], but only asks the local providers.
+    pub async fn get_local_lyrics(&self, track: &Track) -> Result<FetchedLyrics, LyricsError> {
+        self.get_lyrics_from(track, true).await
+    }
+
+    async fn get_lyrics_from(
+        &self,
+        track: &Track,
+        local: bool,
+    ) -> Result<FetchedLyrics, LyricsError> {
+        let mut error = None;
+        // The best of the others, good matches first.
+        let mut fallback: Option<((bool, f64), FetchedLyrics)> = None;
+        for provider in self
+            .providers
+            .iter()
+            .filter(|provider| provider.is_local() == local)
+        {
+            match provider.get_lyrics(track).await {
+                Ok(mut candidates) => {
+                    // Not lyrics, but a page of a proxy or a captive portal.
+                    candidates.retain(|candidate| !is_web_page(&candidate.content));
+                    // E.g. lyrics starting after the end of the track.
+                    candidates.retain(|candidate| {
+                        !is_for_another_track(&candidate.content, track.duration)
+                    });
+                    let best = rank(track, candidates).into_iter().next();
+                    if let Some((score, candidate)) = best {
+                        let fetched = FetchedLyrics {
+                            provider: provider.name().to_string(),
+                            content: candidate.content,
+                            translation: candidate.translation,
+                            poor_match: score < MIN_SCORE,
+                        };
+                        let is_plain = Format::detect(&fetched.content) == Format::Plain;
+                        if score >= MIN_SCORE && !is_plain {
+                            info!("Found lyrics from {} ({:.2})", provider.name(), score);
+                            return Ok(fetched);
+                        }
+                        if is_plain {
+                            info!("Plain text from {} ({:.2})", provider.name(), score);
+                        } else {
+                            info!("Poor match from {} ({:.2})", provider.name(), score);
+                        }
+                        let rank = (score >= MIN_SCORE, score);
+                        if fallback.as_ref().map(|(r, _)| rank > *r).unwrap_or(true) {
+                            fallback = Some((rank, fetched));
+                        }
+                    }
+                }
+                Err(LyricsError::NotFound) => {}
+                Err(e) => {
+                    warn!("Provider {} failed: {:?}", provider.name(), e);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match fallback {
+            Some((_, fetched)) => Ok(fetched),
+            None => Err(error.unwrap_or(LyricsError::NotFound)),
+        }
+    }
+}
@@ -1,100 +1,728 @@
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::bail;
+use anyhow::Result;
 use log::error;
 use log::info;
 use log::warn;
-use lrc::Lyrics;
-use regex::Regex;
-use reqwest::blocking::Client;
-use std::error::Error;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
 
+use super::format;
+use super::format::Parsed;
+use super::plain;
+use super::query_variants;
+use super::romanize;
+use super::romanize::detect_lyrics_script;
+use super::validate::validate;
+use super::validate::Severity;
+use super::validate::Validated;
+use super::CacheEntry;
+use super::ChineseConverter;
+use super::CorrectionStore;
+use super::FetchedLyrics;
+use super::Lyrics;
+use super::LyricsCache;
+use super::LyricsError;
+use super::Metadata;
+use super::ProviderChain;
+use super::QueryVariant;
+use super::Track;
+use crate::config::Config;
+use crate::config::RomanizationDisplay;
+use crate::paths::cache_directory;
+use crate::paths::corrections_directory;
+
+const RETRY_DELAY_INITIAL: Duration = Duration::from_secs(2);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// How often to look for synced lyrics while showing estimated ones.
+const ESTIMATED_RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Looks up lyrics in the background.
+///
+/// Each track change cancels the lookup in flight and starts a new one. Once
+/// it completes, the `notify` callback is invoked from a worker thread, after
+/// which [`Query::take_lyrics`] returns the result. If only plain text turns
+/// up, the lookup goes on every so often, and synced lyrics found later come
+/// as another result.
 pub struct Query {
-    client: Client,
+    runtime: Runtime,
+    lookup: Arc<Lookup>,
+    notify: Arc<dyn Fn() + Send + Sync>,
+    sender: Sender<(u64, Option<Lyrics>)>,
+    receiver: Receiver<(u64, Option<Lyrics>)>,
     last_query: String,
+    generation: u64,
+    task: Option<JoinHandle<()>>,
+}
+
+struct Lookup {
+    providers: ProviderChain,
+    cache: Option<LyricsCache>,
+    corrections: Option<CorrectionStore>,
+    options: TextOptions,
+}
+
+/// Extra texts derived from the fetched lyrics, and which of their lines to
+/// keep.
+///
+/// The cache keeps the documents as they were fetched, so that changing
+/// these options takes effect without fetching them again.
+#[derive(Default)]
+pub struct TextOptions {
+    /// Converts Chinese lines to the script the user reads.
+    pub chinese: Option<ChineseConverter>,
+    /// Whether to spell out Chinese, Japanese and Korean lines in Latin letters.
+    pub romanize: bool,
+    /// Empty lines lasting no longer than this are dropped, so that the line
+    /// before them stays on screen.
+    pub min_gap: Duration,
 }
 
-fn convert_lyrics(lyrics: String) -> Result<String, Box<dyn Error>> {
-    let re = Regex::new(r"\[(\d{2}:\d{2}\.\d{2})\d\]")?;
-    let result = re.replace_all(&lyrics, "[$1]");
-    Ok(result.to_string())
+impl TextOptions {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            chinese: config
+                .lyrics
+                .chinese
+                .map(ChineseConverter::new)
+                .transpose()?,
+            romanize: config.display.romanization != RomanizationDisplay::Off,
+            min_gap: Duration::from_secs_f64(config.display.min_gap.max(0.)),
+        })
+    }
+
+    fn apply(&self, mut lyrics: Lyrics) -> Lyrics {
+        // Decided for the whole lyrics, since lines in kanji only can't be
+        // told apart from Chinese on their own.
+        let script = detect_lyrics_script(lyrics.lines().iter().map(|line| line.text.as_str()));
+        let translation_script = detect_lyrics_script(
+            lyrics
+                .lines()
+                .iter()
+                .filter_map(|line| line.translation.as_deref()),
+        );
+        if let Some(chinese) = self.chinese.as_ref() {
+            lyrics =
+                lyrics.map_lines(|line| chinese.convert_line(line, script, translation_script));
+        }
+        if self.romanize {
+            lyrics = lyrics.map_lines(|mut line| {
+                line.romanization = romanize(&line.text, script);
+                line
+            });
+        }
+        lyrics
+    }
+}
+
+fn parse_lyrics(
+    body: &str,
+    translation: Option<&str>,
+    duration: Option<Duration>,
+    min_gap: Duration,
+) -> Result<Lyrics> {
+    let Parsed {
+        lyrics: mut downloaded_lyrics,
+        diagnostics,
+        out_of_order,
+    } = format::parse(body);
+    for diagnostic in diagnostics.iter() {
+        warn!("Skipped {}", diagnostic);
+    }
+    if downloaded_lyrics.is_empty() {
+        bail!("No timed lines");
+    }
+    if let Some(duration) = duration {
+        downloaded_lyrics = plain::estimate(downloaded_lyrics, duration);
+    }
+    if let Some(translation) = translation {
+        let Parsed {
+            lyrics: translation,
+            diagnostics,
+            ..
+        } = format::parse(translation);
+        for diagnostic in diagnostics.iter() {
+            warn!("Skipped {} of the translation", diagnostic);
+        }
+        downloaded_lyrics = downloaded_lyrics.with_translation(&translation);
+    }
+    let Validated {
+        lyrics: downloaded_lyrics,
+        findings,
+    } = validate(downloaded_lyrics, &out_of_order, duration);
+    for finding in findings.iter() {
+        match finding.severity {
+            Severity::Info => info!("{}", finding),
+            Severity::Warning => warn!("{}", finding),
+            Severity::Error => error!("{}", finding),
+        }
+    }
+    // Probably for another track; better none than these.
+    if let Some(finding) = findings
+        .iter()
+        .find(|finding| finding.severity == Severity::Error)
+    {
+        bail!("{}", finding.message);
+    }
+    let mut lines = vec![];
+    let timed_lines = downloaded_lyrics.lines();
+    for (i, line) in timed_lines.iter().enumerate() {
+        let decoded_line =
+            line.map_text(|text| html_escape::decode_html_entities(text).into_owned());
+        // Skip empty lines that last no longer than the minimum gap.
+        if decoded_line.text.is_empty() && i < timed_lines.len() - 1 {
+            let duration = timed_lines[i + 1].time - line.time;
+            if duration <= min_gap {
+                continue;
+            }
+        }
+        lines.push(decoded_line);
+    }
+    Ok(Lyrics::new(lines).with_metadata(downloaded_lyrics.metadata().clone()))
+}
+
+/// Marks `lyrics` as the best of poor matches, see [`Metadata::poor_match`].
+fn with_poor_match(lyrics: Lyrics, poor_match: bool) -> Lyrics {
+    let metadata = Metadata {
+        poor_match,
+        ..lyrics.metadata().clone()
+    };
+    lyrics.with_metadata(metadata)
+}
+
+fn retry_delay(attempts: u32) -> Duration {
+    RETRY_DELAY_INITIAL
+        .checked_mul(1 << (attempts - 1).min(16))
+        .unwrap_or(RETRY_DELAY_MAX)
+        .min(RETRY_DELAY_MAX)
 }
 
 impl Query {
-    pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .unwrap();
-        Self {
-            client,
+    pub fn new(config: &Config, notify: impl Fn() + Send + Sync + 'static) -> Result<Self> {
+        Self::with_providers(
+            ProviderChain::from_config(config)?,
+            Some(LyricsCache::new(cache_directory())),
+            Some(CorrectionStore::new(corrections_directory())),
+            TextOptions::from_config(config)?,
+            notify,
+        )
+    }
+
+    pub fn with_providers(
+        providers: ProviderChain,
+        cache: Option<LyricsCache>,
+        corrections: Option<CorrectionStore>,
+        options: TextOptions,
+        notify: impl Fn() + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()?;
+        let (sender, receiver) = channel();
+        Ok(Self {
+            runtime,
+            lookup: Arc::new(Lookup {
+                providers,
+                cache,
+                corrections,
+                options,
+            }),
+            notify: Arc::new(notify),
+            sender,
+            receiver,
             last_query: String::from(""),
+            generation: 0,
+            task: None,
+        })
+    }
+
+    /// Starts looking up the lyrics of `track` if it differs from the last one.
+    ///
+    /// Returns whether the track has changed, in which case the lyrics of the
+    /// previous track should no longer be shown.
+    pub fn set_track(&mut self, track: &Track) -> bool {
+        let query = format!("{} {}", track.name, track.artist);
+        if self.last_query == query {
+            return false;
+        }
+        self.last_query = query;
+        self.generation += 1;
+        if let Some(task) = self.task.take() {
+            task.abort();
         }
+        if track.name.is_empty() || track.artist.is_empty() {
+            return true;
+        }
+        info!("{}", &self.last_query);
+        let generation = self.generation;
+        let lookup = self.lookup.clone();
+        let notify = self.notify.clone();
+        let sender = self.sender.clone();
+        let track = track.clone();
+        self.task = Some(self.runtime.spawn(async move {
+            let lyrics = lookup.run(&track).await;
+            let estimated = lyrics
+                .as_ref()
+                .map(|lyrics| lyrics.metadata().estimated)
+                .unwrap_or(false);
+            if sender.send((generation, lyrics)).is_ok() {
+                notify();
+            }
+            // Until the track changes and the task is aborted.
+            if estimated {
+                let lyrics = lookup.wait_for_synced(&track).await;
+                if sender.send((generation, Some(lyrics))).is_ok() {
+                    notify();
+                }
+            }
+        }));
+        true
     }
 
-    // TODO: async
-    // TODO: refactor
-    pub fn get_lyrics(
-        &mut self,
-        name: &str,
-        artist: &str,
-        lyrics: &mut Option<Lyrics>,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        let query = format!("{} {}", name, artist);
-        if self.last_query != query {
-            self.last_query = query;
-            if name.is_empty() || artist.is_empty() {
-                *lyrics = None;
-                return Ok(true);
-            }
-            info!("{}", &self.last_query);
-            *lyrics = None;
-            let response = self
-                .client
-                .get("https://lyrics-api.lujjjh.com/")
-                .query(&[("name", name), ("artist", artist)])
-                .send()
-                .map_err(|e| {
-                    error!("Network error: {:?}", e);
-                    e
-                })?
-                .error_for_status()
-                .map_err(|e| {
-                    warn!("Bad status: {:?}", e);
-                    e
-                })?;
-            let mut body = response.text().map_err(|e| {
-                error!("Failed to read response body: {:?}", e);
-                e
-            })?;
+    /// Forgets the current track, so that its lyrics are looked up again on
+    /// the next call to [`Query::set_track`], e.g. after they were corrected.
+    pub fn forget_track(&mut self) {
+        self.last_query.clear();
+    }
+
+    /// Looks up the lyrics of `track` right away, blocking until done. Unlike
+    /// [`Query::set_track`], failures are not retried.
+    pub fn get_lyrics(&self, track: &Track) -> Result<Lyrics, LyricsError> {
+        self.runtime
+            .block_on(self.lookup.lookup(track, false))
+            .map(|lyrics| self.display(lyrics))
+    }
+
+    /// Converts and romanizes `lyrics` as set, for showing them.
+    pub fn display(&self, lyrics: Lyrics) -> Lyrics {
+        self.lookup.options.apply(lyrics)
+    }
+
+    /// Returns the result of the lookup of the current track, if it has
+    /// completed since the last call. Results of previous tracks are dropped.
+    ///
+    /// The lyrics are as looked up, to be passed to [`Query::display`].
+    pub fn take_lyrics(&mut self) -> Option<Option<Lyrics>> {
+        let mut result = None;
+        while let Ok((generation, lyrics)) = self.receiver.try_recv() {
+            if generation == self.generation {
+                result = Some(lyrics);
+            }
+        }
+        result
+    }
+}
+
+impl Lookup {
+    /// Looks up the lyrics of `track`, retrying transient failures with
+    /// exponential backoff until the task is cancelled.
+    async fn run(&self, track: &Track) -> Option<Lyrics> {
+        let mut attempts = 0;
+        loop {
+            match self.lookup(track, false).await {
+                Ok(lyrics) => return Some(lyrics),
+                Err(e) if e.is_transient() => {
+                    error!("Failed to get lyrics: {:?}", e);
+                    attempts += 1;
+                    let delay = retry_delay(attempts);
+                    info!("Retry #{} in {:?}", attempts, delay);
+                    sleep(delay).await;
+                }
+                Err(LyricsError::NotFound) => {
+                    info!("Not found");
+                    return None;
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Looks up the lyrics of `track` every so often until synced lyrics turn
+    /// up, to replace the estimated ones being shown. The plain text in the
+    /// cache is not used meanwhile, or they would never turn up.
+    async fn wait_for_synced(&self, track: &Track) -> Lyrics {
+        loop {
+            sleep(ESTIMATED_RECHECK_INTERVAL).await;
+            match self.lookup(track, true).await {
+                Ok(lyrics) if !lyrics.metadata().estimated => {
+                    info!("Found synced lyrics");
+                    return lyrics;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Looks up the lyrics of `track`. With `recheck`, only synced lyrics are
+    /// taken from the cache.
+    async fn lookup(&self, track: &Track, recheck: bool) -> Result<Lyrics, LyricsError> {
+        let corrected = self
+            .corrections
+            .as_ref()
+            .and_then(|corrections| corrections.get(&track.key()));
+        if let Some(lyrics) = corrected {
+            info!("Found corrected lyrics");
+            return Ok(lyrics);
+        }
+        // Local files take precedence over the cache, so that edits to them
+        // show up at once.
+        let estimated = match self.fetch_local(track).await {
+            Ok(fetched) => {
+                match self
+                    .parse(&fetched.content, fetched.translation.as_deref(), track)
+                    .map(|lyrics| with_poor_match(lyrics, fetched.poor_match))
+                {
+                    // Plain text only counts if nothing is synced.
+                    Ok(lyrics) if lyrics.metadata().estimated => Some(lyrics),
+                    Ok(lyrics) => return Ok(lyrics),
+                    // Files we can't read are of no use; ask the others.
+                    Err(_) => None,
+                }
+            }
+            Err(LyricsError::NotFound) => None,
+            Err(e) => {
+                warn!("Failed to read local lyrics: {:?}", e);
+                None
+            }
+        };
+        match (self.lookup_remote(track, recheck).await, estimated) {
+            (Ok(lyrics), Some(estimated)) if lyrics.metadata().estimated => Ok(estimated),
+            (Ok(lyrics), _) => Ok(lyrics),
+            (Err(_), Some(estimated)) => Ok(estimated),
+            (Err(e), None) => Err(e),
+        }
+    }
 
-            body = convert_lyrics(body)?;
+    /// Looks up the lyrics of `track` in the cache, then from the remote
+    /// providers.
+    async fn lookup_remote(&self, track: &Track, recheck: bool) -> Result<Lyrics, LyricsError> {
+        let key = track.key();
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get(&key))
+            .filter(|entry| !recheck || entry.is_synced());
+        if let Some(entry) = cached {
+            info!("Cache hit ({})", entry.provider);
+            let poor_match = entry.poor_match;
+            return match entry.lyrics {
+                Some(content) => self
+                    .parse(&content, entry.translation.as_deref(), track)
+                    .map(|lyrics| with_poor_match(lyrics, poor_match)),
+                None => Err(LyricsError::NotFound),
+            };
+        }
+        let mut variants = query_variants(track);
+        let previous_variant = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.peek(&key))
+            .and_then(|entry| entry.variant);
+        if let Some(variant) = previous_variant {
+            variants.retain(|v| v != &variant);
+            variants.insert(0, variant);
+        }
+        match self.fetch(track, &variants).await {
+            Ok((variant, fetched)) => {
+                let entry = CacheEntry::new(&key, &fetched, &variant);
+                let result = self
+                    .parse(&fetched.content, fetched.translation.as_deref(), track)
+                    .map(|lyrics| with_poor_match(lyrics, fetched.poor_match));
+                // Don't cache documents that we can't make use of.
+                if result.is_ok() {
+                    self.put_cache(entry);
+                }
+                result
+            }
+            Err(LyricsError::NotFound) => {
+                self.put_cache(CacheEntry::not_found(&key));
+                Err(LyricsError::NotFound)
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-            let downloaded_lyrics = Lyrics::from_str(body).map_err(|e| {
+    /// Tries each query variant in order until one of them hits.
+    async fn fetch(
+        &self,
+        track: &Track,
+        variants: &[QueryVariant],
+    ) -> Result<(QueryVariant, FetchedLyrics), LyricsError> {
+        let mut error = None;
+        for variant in variants.iter() {
+            info!("Trying {} - {}", variant.artist, variant.name);
+            match self
+                .providers
+                .get_lyrics(&track.with_variant(variant))
+                .await
+            {
+                Ok(fetched) => return Ok((variant.clone(), fetched)),
+                Err(LyricsError::NotFound) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        Err(error.unwrap_or(LyricsError::NotFound))
+    }
+
+    async fn fetch_local(&self, track: &Track) -> Result<FetchedLyrics, LyricsError> {
+        for variant in query_variants(track).iter() {
+            match self
+                .providers
+                .get_local_lyrics(&track.with_variant(variant))
+                .await
+            {
+                Err(LyricsError::NotFound) => {}
+                result => return result,
+            }
+        }
+        Err(LyricsError::NotFound)
+    }
+
+    /// Parses a document, spreading plain text over the length of `track`.
+    fn parse(
+        &self,
+        content: &str,
+        translation: Option<&str>,
+        track: &Track,
+    ) -> Result<Lyrics, LyricsError> {
+        let lyrics = parse_lyrics(content, translation, track.duration, self.options.min_gap)
+            .map_err(|e| {
                 error!("Failed to parse lyrics: {:?}", e);
-                e
+                LyricsError::Parse(e)
             })?;
-            info!("OK");
-            let mut new_lyrics = Lyrics::new();
-            let timed_lines = downloaded_lyrics.get_timed_lines();
-            for (i, (time_tag, line)) in timed_lines.iter().enumerate() {
-                let line = html_escape::decode_html_entities(&line);
-                let text = line.trim();
-                // Skip empty lines that last no longer than 3s.
-                if text.is_empty() {
-                    if i < timed_lines.len() - 1 {
-                        let duration =
-                            timed_lines[i + 1].0.get_timestamp() - time_tag.get_timestamp();
-                        if duration <= 3000 {
-                            continue;
-                        }
-                    }
-                }
-                new_lyrics.add_timed_line(time_tag.clone(), line)?;
+        info!("OK");
+        Ok(lyrics)
+    }
+
+    fn put_cache(&self, entry: CacheEntry) {
+        if let Some(cache) = self.cache.as_ref() {
+            if let Err(e) = cache.put(&entry) {
+                warn!("Failed to cache lyrics: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Instant;
+
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::lyrics::provider::LyricsProvider;
+    use crate::lyrics::Candidate;
+
+    const LRC: &str = "[00:01.00]Hello world\n[00:03.00]Second line\n";
+
+    /// Fails `failures` times, then has `lyrics` or nothing.
+    struct StubProvider {
+        calls: Arc<AtomicUsize>,
+        failures: usize,
+        lyrics: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl LyricsProvider for StubProvider {
+        fn name(&self) -> &str {
+            "stub"
+        }
+
+        async fn get_lyrics(&self, _track: &Track) -> Result<Vec<Candidate>, LyricsError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(LyricsError::Transient(anyhow!("Connection reset")));
+            }
+            match self.lyrics {
+                Some(lyrics) => Ok(vec![Candidate::new(lyrics.to_string())]),
+                None => Err(LyricsError::NotFound),
             }
-            *lyrics = Some(new_lyrics);
-            Ok(true)
-        } else {
-            Ok(false)
         }
     }
+
+    fn track() -> Track {
+        Track {
+            name: "Hello".to_string(),
+            artist: "iLyrics".to_string(),
+            album: String::new(),
+            album_artist: String::new(),
+            duration: None,
+            location: None,
+        }
+    }
+
+    /// A query of a stub provider, and the number of times it was asked.
+    fn query(
+        failures: usize,
+        lyrics: Option<&'static str>,
+        cache: &Path,
+    ) -> (Query, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = StubProvider {
+            calls: calls.clone(),
+            failures,
+            lyrics,
+        };
+        let query = Query::with_providers(
+            ProviderChain::new().with(provider),
+            Some(LyricsCache::new(cache.to_path_buf())),
+            None,
+            TextOptions::default(),
+            || {},
+        )
+        .unwrap();
+        (query, calls)
+    }
+
+    fn texts(lyrics: &Lyrics) -> Vec<&str> {
+        lyrics
+            .lines()
+            .iter()
+            .map(|line| line.text.as_str())
+            .filter(|text| !text.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn asks_the_next_provider_past_web_pages() {
+        let cache = tempfile::tempdir().unwrap();
+        let page = "<!DOCTYPE html>\n<html><body><p>Please sign in</p></body></html>\n";
+        let providers = ProviderChain::new()
+            .with(StubProvider {
+                calls: Arc::new(AtomicUsize::new(0)),
+                failures: 0,
+                lyrics: Some(page),
+            })
+            .with(StubProvider {
+                calls: Arc::new(AtomicUsize::new(0)),
+                failures: 0,
+                lyrics: Some(LRC),
+            });
+        let query = Query::with_providers(
+            providers,
+            Some(LyricsCache::new(cache.path().to_path_buf())),
+            None,
+            TextOptions::default(),
+            || {},
+        )
+        .unwrap();
+        let lyrics = query.get_lyrics(&track()).unwrap();
+        assert_eq!(texts(&lyrics), ["Hello world", "Second line"]);
+    }
+
+    #[test]
+    fn asks_the_next_provider_past_lyrics_of_another_track() {
+        let cache = tempfile::tempdir().unwrap();
+        let providers = ProviderChain::new()
+            .with(StubProvider {
+                calls: Arc::new(AtomicUsize::new(0)),
+                failures: 0,
+                lyrics: Some("[01:00.00]Hello world\n[01:03.00]Second line\n"),
+            })
+            .with(StubProvider {
+                calls: Arc::new(AtomicUsize::new(0)),
+                failures: 0,
+                lyrics: Some(LRC),
+            });
+        let query = Query::with_providers(
+            providers,
+            Some(LyricsCache::new(cache.path().to_path_buf())),
+            None,
+            TextOptions::default(),
+            || {},
+        )
+        .unwrap();
+        let track = Track {
+            duration: Some(Duration::from_secs(10)),
+            ..track()
+        };
+        let lyrics = query.get_lyrics(&track).unwrap();
+        assert_eq!(lyrics.lines()[0].time, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rejects_lyrics_of_another_track() {
+        let result = parse_lyrics(
+            "[01:00.00]Hello world\n",
+            None,
+            Some(Duration::from_secs(10)),
+            Duration::ZERO,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn caches_lyrics_found() {
+        let cache = tempfile::tempdir().unwrap();
+        let (query, calls) = query(0, Some(LRC), cache.path());
+        let lyrics = query.get_lyrics(&track()).unwrap();
+        assert_eq!(texts(&lyrics), ["Hello world", "Second line"]);
+        let lyrics = query.get_lyrics(&track()).unwrap();
+        assert_eq!(texts(&lyrics), ["Hello world", "Second line"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn caches_lyrics_not_found() {
+        let cache = tempfile::tempdir().unwrap();
+        let (query, calls) = query(0, None, cache.path());
+        assert!(matches!(
+            query.get_lyrics(&track()),
+            Err(LyricsError::NotFound)
+        ));
+        let asked = calls.load(Ordering::SeqCst);
+        assert_eq!(asked, query_variants(&track()).len());
+        let entry = LyricsCache::new(cache.path().to_path_buf())
+            .peek(&track().key())
+            .unwrap();
+        assert_eq!(entry.lyrics, None);
+        assert!(matches!(
+            query.get_lyrics(&track()),
+            Err(LyricsError::NotFound)
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), asked);
+    }
+
+    #[test]
+    fn does_not_cache_transient_failures() {
+        let cache = tempfile::tempdir().unwrap();
+        let (query, _) = query(query_variants(&track()).len(), Some(LRC), cache.path());
+        assert!(query.get_lyrics(&track()).unwrap_err().is_transient());
+        assert!(LyricsCache::new(cache.path().to_path_buf())
+            .peek(&track().key())
+            .is_none());
+        assert!(query.get_lyrics(&track()).is_ok());
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        let cache = tempfile::tempdir().unwrap();
+        let variants = query_variants(&track()).len();
+        let (mut query, calls) = query(variants, Some(LRC), cache.path());
+        assert!(query.set_track(&track()));
+        let deadline = Instant::now() + RETRY_DELAY_INITIAL * 5;
+        let lyrics = loop {
+            if let Some(lyrics) = query.take_lyrics() {
+                break lyrics;
+            }
+            assert!(Instant::now() < deadline, "no lyrics after the retry");
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!(texts(&lyrics.unwrap()), ["Hello world", "Second line"]);
+        assert_eq!(calls.load(Ordering::SeqCst), variants + 1);
+    }
 }
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use super::Lyrics;
+use super::Metadata;
+
+/// When a line of lyrics is actually sung: the line at index `line` of the
+/// lyrics starts at `time` in the track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anchor {
+    pub line: usize,
+    pub time: Duration,
+}
+
+/// Retimes `lyrics` made for another edit of the track, e.g. the album
+/// version of a radio edit or of a live take at another tempo, so that each
+/// anchored line starts when it is actually sung.
+///
+/// Two anchors give an affine map, which shifts and stretches all times
+/// alike; more give a piecewise-linear one, straight between each pair of
+/// neighboring anchors. Times before the first anchor or after the last
+/// follow the nearest piece. All times are moved, word timings included,
+/// and the `[offset:]` of the lyrics is applied so that the result has none.
+/// Of two anchors on the same line, the later one counts.
+pub fn retime(lyrics: &Lyrics, anchors: &[Anchor]) -> Result<Lyrics> {
+    // From the time in the lyrics to the time in the track, in seconds.
+    let mut points: Vec<(f64, f64)> = vec![];
+    for anchor in anchors.iter() {
+        let line = match lyrics.lines().get(anchor.line) {
+            Some(line) => line,
+            None => bail!("No line {} to anchor", anchor.line),
+        };
+        let from = line.time.as_secs_f64();
+        points.retain(|point| point.0 != from);
+        points.push((from, anchor.time.as_secs_f64()));
+    }
+    points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    if points.len() < 2 {
+        bail!("At least two anchors at different times are needed");
+    }
+    if points.windows(2).any(|pair| pair[1].1 <= pair[0].1) {
+        bail!("The anchors are out of order");
+    }
+    let map = |time: Duration| {
+        let time = time.as_secs_f64();
+        let piece = points
+            .partition_point(|point| point.0 <= time)
+            .clamp(1, points.len() - 1);
+        let (from_start, to_start) = points[piece - 1];
+        let (from_end, to_end) = points[piece];
+        let slope = (to_end - to_start) / (from_end - from_start);
+        Duration::from_secs_f64((to_start + (time - from_start) * slope).max(0.))
+    };
+    let retimed = lyrics.clone().map_lines(|mut line| {
+        line.time = map(line.time);
+        line.end = line.end.map(map);
+        for word in line.words.iter_mut().chain(line.background.iter_mut()) {
+            word.time = map(word.time);
+        }
+        line
+    });
+    Ok(retimed.with_metadata(Metadata {
+        offset: 0,
+        // The lyrics no longer fit the recording they were made for.
+        length: None,
+        ..lyrics.metadata().clone()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::lrc;
+
+    const LRC: &str =
+        "[offset:500]\n[00:10.00]a\n[00:20.00]<00:20.00>b <00:25.00>c\n[00:30.00]d\n[00:40.00]e\n";
+
+    fn anchor(line: usize, seconds: u64) -> Anchor {
+        Anchor {
+            line,
+            time: Duration::from_secs(seconds),
+        }
+    }
+
+    fn times(lyrics: &Lyrics) -> Vec<f64> {
+        lyrics
+            .lines()
+            .iter()
+            .map(|line| line.time.as_secs_f64())
+            .collect()
+    }
+
+    #[test]
+    fn shifts_and_stretches_with_two_anchors() {
+        let lyrics = lrc::parse(LRC).lyrics;
+        let shifted = retime(&lyrics, &[anchor(0, 12), anchor(2, 32)]).unwrap();
+        assert_eq!(times(&shifted), [12., 22., 32., 42.]);
+        let words = shifted.lines()[1]
+            .words
+            .iter()
+            .map(|word| word.time.as_secs_f64())
+            .collect::<Vec<_>>();
+        assert_eq!(words, [22., 27.]);
+        assert_eq!(shifted.metadata().offset, 0);
+        let stretched = retime(&lyrics, &[anchor(0, 10), anchor(2, 40)]).unwrap();
+        assert_eq!(times(&stretched), [10., 25., 40., 55.]);
+    }
+
+    #[test]
+    fn follows_each_piece_between_anchors() {
+        let lyrics = lrc::parse(LRC).lyrics;
+        let retimed = retime(&lyrics, &[anchor(0, 10), anchor(1, 20), anchor(3, 60)]).unwrap();
+        assert_eq!(times(&retimed), [10., 20., 40., 60.]);
+    }
+
+    #[test]
+    fn takes_the_later_of_two_anchors_on_a_line() {
+        let lyrics = lrc::parse(LRC).lyrics;
+        let retimed = retime(&lyrics, &[anchor(0, 5), anchor(2, 30), anchor(0, 10)]).unwrap();
+        assert_eq!(times(&retimed), [10., 20., 30., 40.]);
+    }
+
+    #[test]
+    fn rejects_anchors_it_cant_use() {
+        let lyrics = lrc::parse(LRC).lyrics;
+        assert!(retime(&lyrics, &[anchor(0, 10)]).is_err());
+        assert!(retime(&lyrics, &[anchor(0, 10), anchor(0, 20)]).is_err());
+        assert!(retime(&lyrics, &[anchor(0, 20), anchor(1, 10)]).is_err());
+        assert!(retime(&lyrics, &[anchor(0, 10), anchor(9, 20)]).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::Mutex;
+
+use lindera::tokenizer::Tokenizer;
+use log::warn;
+use once_cell::sync::Lazy;
+use pinyin::ToPinyin;
+use wana_kana::to_romaji::to_romaji;
+
+/// The writing systems that we can romanize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Script {
+    Chinese,
+    Japanese,
+    Korean,
+}
+
+/// A morphological analyzer with the IPADIC dictionary built in, which gives
+/// the readings of Japanese words.
+static TOKENIZER: Lazy<Option<Mutex<Tokenizer>>> = Lazy::new(|| {
+    Tokenizer::new()
+        .map(Mutex::new)
+        .map_err(|e| warn!("Failed to load the Japanese dictionary: {:?}", e))
+        .ok()
+});
+
+const INITIALS: [&str; 19] = [
+    "g", "kk", "n", "d", "tt", "r", "m", "b", "pp", "s", "ss", "", "j", "jj", "ch", "k", "t", "p",
+    "h",
+];
+
+const MEDIALS: [&str; 21] = [
+    "a", "ae", "ya", "yae", "eo", "e", "yeo", "ye", "o", "wa", "wae", "oe", "yo", "u", "wo", "we",
+    "wi", "yu", "eu", "ui", "i",
+];
+
+/// Final consonants at the end of a word or before another consonant.
+const FINALS: [&str; 28] = [
+    "", "k", "k", "k", "n", "n", "n", "t", "l", "k", "m", "l", "l", "l", "p", "l", "m", "p", "p",
+    "t", "t", "ng", "t", "t", "k", "t", "p", "t",
+];
+
+/// Final consonants carried over to a following syllable without an initial
+/// consonant, e.g. 한국어 is hangugeo, not hangukeo.
+const FINALS_LIAISON: [&str; 28] = [
+    "", "g", "kk", "ks", "n", "nj", "n", "d", "r", "lg", "lm", "lb", "ls", "lt", "lp", "r", "m",
+    "b", "ps", "s", "ss", "ng", "j", "ch", "k", "t", "p", "",
+];
+
+const HANGUL_SYLLABLES: std::ops::RangeInclusive<char> = '\u{ac00}'..='\u{d7a3}';
+
+pub fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}')
+}
+
+pub fn is_hangul(c: char) -> bool {
+    matches!(c, '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7a3}')
+}
+
+pub fn is_han(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' | '\u{20000}'..='\u{2ffff}')
+}
+
+/// Guesses the script of a line from its characters.
+///
+/// Kana tell Japanese apart from Chinese, since both use Han characters, but
+/// some Japanese lines are written in kanji only; see [`detect_lyrics_script`].
+pub fn detect_script(text: &str) -> Option<Script> {
+    if text.chars().any(is_kana) {
+        Some(Script::Japanese)
+    } else if text.chars().any(is_hangul) {
+        Some(Script::Korean)
+    } else if text.chars().any(is_han) {
+        Some(Script::Chinese)
+    } else {
+        None
+    }
+}
+
+/// Guesses the script of a whole document from the texts of its lines: any
+/// kana make it Japanese, even though most of its lines may be in kanji only.
+pub fn detect_lyrics_script<'a>(texts: impl IntoIterator<Item = &'a str>) -> Option<Script> {
+    let scripts = texts
+        .into_iter()
+        .filter_map(detect_script)
+        .collect::<Vec<_>>();
+    [Script::Japanese, Script::Korean, Script::Chinese]
+        .iter()
+        .copied()
+        .find(|script| scripts.contains(script))
+}
+
+/// Transliterates `text` into Latin letters: pinyin with tone marks for
+/// Chinese, romaji for Japanese and the Revised Romanization for Korean.
+/// `script` is that of the whole lyrics, which tells whether lines in Han
+/// characters only are Chinese or Japanese.
+///
+/// Works offline with built-in dictionaries. Returns `None` if the text is in
+/// none of these scripts.
+pub fn romanize(text: &str, script: Option<Script>) -> Option<String> {
+    let romanized = match (detect_script(text)?, script) {
+        (Script::Japanese, _) | (Script::Chinese, Some(Script::Japanese)) => {
+            romanize_japanese(text)
+        }
+        (Script::Chinese, _) => romanize_chinese(text),
+        (Script::Korean, _) => romanize_korean(text),
+    };
+    Some(romanized.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Spells out each Han character as a syllable, keeping everything else.
+fn romanize_chinese(text: &str) -> String {
+    let mut romanized = String::new();
+    let mut after_syllable = false;
+    for (c, pinyin) in text.chars().zip(text.to_pinyin()) {
+        match pinyin {
+            Some(pinyin) => {
+                if !romanized.is_empty() && !romanized.ends_with(' ') {
+                    romanized.push(' ');
+                }
+                romanized.push_str(pinyin.with_tone());
+                after_syllable = true;
+            }
+            None => {
+                if after_syllable && c.is_alphanumeric() {
+                    romanized.push(' ');
+                }
+                romanized.push(c);
+                after_syllable = false;
+            }
+        }
+    }
+    romanized
+}
+
+/// Spells out the readings of the words, so that kanji are read in context.
+fn romanize_japanese(text: &str) -> String {
+    let tokens = TOKENIZER.as_ref().and_then(|tokenizer| {
+        tokenizer
+            .lock()
+            .unwrap()
+            .tokenize(text)
+            .map_err(|e| warn!("Failed to tokenize {}: {:?}", text, e))
+            .ok()
+    });
+    match tokens {
+        Some(tokens) => tokens
+            .iter()
+            .map(|token| match token.detail.get(7) {
+                // In katakana.
+                Some(reading) if reading != "*" => to_romaji(reading),
+                // Words missing from the dictionary, which may still be kana.
+                _ => to_romaji(token.text),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => to_romaji(text),
+    }
+}
+
+/// Applies the basic rules of the Revised Romanization, with liaison but
+/// without the other sound changes.
+fn romanize_korean(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut romanized = String::new();
+    let mut carried = None;
+    for (i, &c) in chars.iter().enumerate() {
+        if !HANGUL_SYLLABLES.contains(&c) {
+            romanized.push(c);
+            continue;
+        }
+        let code = c as usize - 0xac00;
+        let (initial, medial, last) = (code / 588, code % 588 / 28, code % 28);
+        match carried.take() {
+            Some(onset) => romanized.push_str(onset),
+            None => romanized.push_str(INITIALS[initial]),
+        }
+        romanized.push_str(MEDIALS[medial]);
+        let followed_by_vowel = chars
+            .get(i + 1)
+            .filter(|&&next| HANGUL_SYLLABLES.contains(&next))
+            .map(|&next| (next as usize - 0xac00) / 588 == 11)
+            .unwrap_or(false);
+        // ㅇ keeps its sound before a vowel.
+        if followed_by_vowel && last != 21 {
+            carried = Some(FINALS_LIAISON[last]);
+        } else {
+            romanized.push_str(FINALS[last]);
+        }
+    }
+    romanized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_japanese_from_any_kana() {
+        let script = detect_lyrics_script(vec!["世界", "こんにちは", "Hello"]);
+        assert_eq!(script, Some(Script::Japanese));
+        assert_eq!(
+            detect_lyrics_script(vec!["世界", "Hello"]),
+            Some(Script::Chinese)
+        );
+        assert_eq!(detect_lyrics_script(vec!["Hello"]), None);
+    }
+
+    #[test]
+    fn leaves_lines_without_these_scripts() {
+        assert_eq!(romanize("Hello", Some(Script::Japanese)), None);
+    }
+
+    #[test]
+    fn spells_out_pinyin_with_tone_marks() {
+        assert_eq!(
+            romanize("你好世界", Some(Script::Chinese)).as_deref(),
+            Some("nǐ hǎo shì jiè")
+        );
+        assert_eq!(
+            romanize("我爱你 baby", Some(Script::Chinese)).as_deref(),
+            Some("wǒ ài nǐ baby")
+        );
+    }
+
+    #[test]
+    fn spells_out_the_readings_of_japanese() {
+        // lindera builds a dummy dictionary when DOCS_RS is set.
+        if option_env!("DOCS_RS").is_some() {
+            return;
+        }
+        assert_eq!(
+            romanize("さくら", Some(Script::Japanese)).as_deref(),
+            Some("sakura")
+        );
+        assert_eq!(
+            romanize("日本語", Some(Script::Japanese)).as_deref(),
+            Some("nihongo")
+        );
+        // Han characters only, but the lyrics are Japanese.
+        assert_ne!(
+            romanize("日本語", Some(Script::Japanese)),
+            romanize("日本語", Some(Script::Chinese))
+        );
+    }
+
+    #[test]
+    fn links_final_consonants_to_following_vowels() {
+        assert_eq!(
+            romanize("한국어", Some(Script::Korean)).as_deref(),
+            Some("hangugeo")
+        );
+        assert_eq!(
+            romanize("음악", Some(Script::Korean)).as_deref(),
+            Some("eumak")
+        );
+        assert_eq!(
+            romanize("사랑해요", Some(Script::Korean)).as_deref(),
+            Some("saranghaeyo")
+        );
+    }
+}
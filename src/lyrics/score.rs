@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use super::format;
+use super::format::Format;
+use super::normalize::clean_title;
+use super::normalize::primary_artist;
+use super::Candidate;
+use super::Track;
+
+/// Candidates scoring below this are only used if nothing better turns up.
+pub const MIN_SCORE: f64 = 0.5;
+
+/// How well `candidate` matches `track`, from 0 to 1.
+///
+/// Compares the metadata the provider reported, falling back to the ID tags of
+/// the document, and checks that the lyrics fit into the length of the track,
+/// which tells different edits apart.
+pub fn score(track: &Track, candidate: &Candidate) -> f64 {
+    let lyrics = format::parse(&candidate.content).lyrics;
+    let metadata = lyrics.metadata();
+    let name = candidate.name.as_ref().or(metadata.title.as_ref());
+    let artist = candidate.artist.as_ref().or(metadata.artist.as_ref());
+    let album = candidate.album.as_ref().or(metadata.album.as_ref());
+    let candidate_duration = candidate.duration.or(metadata.length);
+    // The times of plain text are made up.
+    let last_line = lyrics
+        .lines()
+        .last()
+        .filter(|_| !metadata.estimated)
+        .map(|line| line.time);
+    let mut total = 0.;
+    let mut weights = 0.;
+    let mut add = |weight: f64, value: Option<f64>| {
+        if let Some(value) = value {
+            total += weight * value;
+            weights += weight;
+        }
+    };
+    add(
+        2.,
+        name.map(|name| similarity(&clean_title(&track.name), &clean_title(name))),
+    );
+    add(
+        1.,
+        artist.map(|artist| similarity(&primary_artist(&track.artist), &primary_artist(artist))),
+    );
+    add(
+        0.5,
+        album
+            .filter(|_| !track.album.is_empty())
+            .map(|album| similarity(&clean_title(&track.album), &clean_title(album))),
+    );
+    if let Some(duration) = track.duration {
+        add(
+            1.,
+            candidate_duration.map(|candidate_duration| duration_fit(duration, candidate_duration)),
+        );
+        add(2., last_line.map(|last| length_fit(duration, last)));
+    }
+    if weights == 0. {
+        // Nothing to compare with; trust the provider.
+        1.
+    } else {
+        total / weights
+    }
+}
+
+/// Sorts `candidates` from the best to the worst match of `track`. Among good
+/// matches, synced lyrics come before plain text.
+pub fn rank(track: &Track, candidates: Vec<Candidate>) -> Vec<(f64, Candidate)> {
+    let mut ranked = candidates
+        .into_iter()
+        .map(|candidate| (score(track, &candidate), candidate))
+        .collect::<Vec<_>>();
+    let key = |(score, candidate): &(f64, Candidate)| {
+        let is_synced = Format::detect(&candidate.content) != Format::Plain;
+        (*score >= MIN_SCORE && is_synced, *score)
+    };
+    ranked.sort_by(|a, b| {
+        key(b)
+            .partial_cmp(&key(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    ranked
+}
+
+/// 1 for equal strings, the share of common words otherwise.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a = a.to_lowercase();
+    let b = b.to_lowercase();
+    if a == b {
+        return 1.;
+    }
+    let words_a = a.split_whitespace().collect::<Vec<_>>();
+    let words_b = b.split_whitespace().collect::<Vec<_>>();
+    if words_a.is_empty() || words_b.is_empty() {
+        return 0.;
+    }
+    let common = words_a.iter().filter(|word| words_b.contains(word)).count();
+    common as f64 / words_a.len().max(words_b.len()) as f64
+}
+
+/// 1 if the durations are within a few seconds, falling to 0 at 30 seconds apart.
+fn duration_fit(duration: Duration, candidate_duration: Duration) -> f64 {
+    let difference = duration.abs_diff(candidate_duration).as_secs_f64();
+    (1. - (difference - 3.).max(0.) / 27.).max(0.)
+}
+
+/// 1 if the last line falls into the last part of the track, 0 if it falls
+/// past its end: those lyrics are for a longer version of the song.
+fn length_fit(duration: Duration, last: Duration) -> f64 {
+    if last > duration + Duration::from_secs(5) {
+        return 0.;
+    }
+    let ratio = last.as_secs_f64() / duration.as_secs_f64().max(1.);
+    (ratio / 0.6).min(1.)
+}
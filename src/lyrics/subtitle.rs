@@ -0,0 +1,398 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::format::merge_translations;
+use super::format::out_of_order;
+use super::format::Diagnostic;
+use super::format::Parsed;
+use super::romanize::detect_script;
+use super::Line;
+use super::Lyrics;
+use super::Word;
+
+/// A tag of SRT or WebVTT markup, e.g. `<i>`, `</b>`, `<v Alice>` or a
+/// karaoke timestamp `<00:12.500>`.
+static CUE_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<(/?)([^>\s.]*)([^>]*)>").unwrap());
+
+/// An override block of ASS, e.g. `{\k50}` or `{\an8\i1}`.
+static OVERRIDE_BLOCK: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([^}]*)\}").unwrap());
+
+/// A karaoke tag of ASS: `\k`, `\K`, `\kf` or `\ko`, with the duration of the
+/// following syllable in centiseconds.
+static KARAOKE_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"\\(?:k|K|kf|ko)(\d+)").unwrap());
+
+const ASS_DEFAULT_FORMAT: &str =
+    "Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+/// Parses SubRip subtitles, made of numbered cues like
+///
+/// ```text
+/// 1
+/// 00:00:12,000 --> 00:00:15,500
+/// <i>Hello world</i>
+/// ```
+///
+/// As in bilingual lyric videos, the lines of a cue after the first are taken
+/// as its translation if they are in another script. Otherwise they are the
+/// rest of a long line, wrapped.
+pub fn parse_srt(content: &str) -> Parsed {
+    parse_cues(content, false)
+}
+
+/// Parses WebVTT subtitles. Voice spans (`<v Alice>`) set the singer, and
+/// karaoke timestamps (`<00:12.500>`) the timings of the words.
+pub fn parse_vtt(content: &str) -> Parsed {
+    parse_cues(content, true)
+}
+
+/// Parses the `Dialogue` events of ASS or SSA subtitles. Karaoke tags
+/// (`{\k50}`) set the timings of the syllables and the `Name` field the
+/// singer. Text after `\N` is the translation, as in SRT.
+pub fn parse_ass(content: &str) -> Parsed {
+    let mut lines = vec![];
+    let mut diagnostics = vec![];
+    let mut in_events = false;
+    let mut format = fields(ASS_DEFAULT_FORMAT);
+    for (index, source) in content.lines().enumerate() {
+        let source = source.trim_start_matches('\u{feff}').trim();
+        if source.starts_with('[') {
+            in_events = source.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        let (kind, value) = match source.split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        match kind.trim() {
+            "Format" => format = fields(value),
+            "Dialogue" => {
+                let values = value.splitn(format.len(), ',').collect::<Vec<_>>();
+                let field = |name: &str| {
+                    format
+                        .iter()
+                        .position(|field| field.eq_ignore_ascii_case(name))
+                        .and_then(|index| values.get(index))
+                        .map(|value| value.trim())
+                };
+                let start = field("Start").and_then(parse_timestamp);
+                let end = field("End").and_then(parse_timestamp);
+                let (start, end) = match (start, end) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => {
+                        diagnostics.push(Diagnostic {
+                            line: index + 1,
+                            message: "invalid start or end".to_string(),
+                        });
+                        continue;
+                    }
+                };
+                let text = field("Text").unwrap_or_default();
+                let texts = text
+                    .split("\\N")
+                    .flat_map(|text| text.split("\\n"))
+                    .collect::<Vec<_>>();
+                let mut line = parse_ass_text(texts[0], start, end);
+                match join_texts(texts[1..].iter().map(|text| strip_override_blocks(text))) {
+                    Some(rest) if is_translation(&line.text, &rest) => {
+                        line.translation = Some(rest)
+                    }
+                    Some(_) => line = parse_ass_text(&texts.join(" "), start, end),
+                    None => {}
+                }
+                line.agent = field("Name")
+                    .or_else(|| field("Actor"))
+                    .filter(|name| !name.is_empty())
+                    .map(|name| name.to_string());
+                lines.push(line);
+            }
+            // Comments, styles and so on.
+            _ => {}
+        }
+    }
+    Parsed {
+        out_of_order: out_of_order(lines.iter().map(|line| line.time)),
+        lyrics: Lyrics::new(merge_translations(lines)),
+        diagnostics,
+    }
+}
+
+fn fields(format: &str) -> Vec<String> {
+    format
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .collect()
+}
+
+/// Parses the text of an ASS event, turning karaoke tags into words.
+fn parse_ass_text(text: &str, start: Duration, end: Duration) -> Line {
+    let mut words: Vec<Word> = vec![];
+    let mut time = start;
+    let mut timed = false;
+    let mut last = 0;
+    let push = |words: &mut Vec<Word>, text: &str| match words.last_mut() {
+        Some(word) => word.text.push_str(text),
+        None => words.push(Word::new(start, text)),
+    };
+    for captures in OVERRIDE_BLOCK.captures_iter(text) {
+        let block = captures.get(0).unwrap();
+        push(&mut words, &text[last..block.start()]);
+        last = block.end();
+        for karaoke in KARAOKE_TAG.captures_iter(&captures[1]) {
+            let centiseconds: u64 = match karaoke[1].parse() {
+                Ok(centiseconds) => centiseconds,
+                Err(_) => continue,
+            };
+            words.push(Word::new(time, ""));
+            time += Duration::from_millis(centiseconds * 10);
+            timed = true;
+        }
+    }
+    push(&mut words, &text[last..]);
+    let mut line = Line::new(start, "");
+    line.end = Some(end);
+    if timed {
+        if time < end {
+            words.push(Word::new(time, ""));
+        }
+        words.retain(|word| !word.text.is_empty() || word.time > start);
+        line.set_words(
+            words
+                .into_iter()
+                .map(|word| Word::new(word.time, &word.text.replace("\\h", " ")))
+                .collect(),
+        );
+    } else {
+        line.text = strip_override_blocks(text);
+    }
+    line
+}
+
+fn strip_override_blocks(text: &str) -> String {
+    OVERRIDE_BLOCK
+        .replace_all(text, "")
+        .replace("\\h", " ")
+        .trim()
+        .to_string()
+}
+
+/// Parses the cues of SRT or WebVTT, which only differ in their header and in
+/// the markup WebVTT adds.
+fn parse_cues(content: &str, vtt: bool) -> Parsed {
+    let mut lines = vec![];
+    let mut diagnostics = vec![];
+    let sources = content
+        .lines()
+        .map(|line| line.trim_start_matches('\u{feff}'))
+        .collect::<Vec<_>>();
+    let mut index = 0;
+    while index < sources.len() {
+        // A block runs until the next blank line.
+        let start = index;
+        while index < sources.len() && !sources[index].trim().is_empty() {
+            index += 1;
+        }
+        let block = &sources[start..index];
+        index += 1;
+        if block.is_empty() {
+            continue;
+        }
+        if vtt
+            && ["WEBVTT", "NOTE", "STYLE", "REGION"]
+                .iter()
+                .any(|keyword| block[0].starts_with(keyword))
+        {
+            continue;
+        }
+        // Skip the number or the identifier of the cue.
+        let timing = match block.iter().position(|line| line.contains("-->")) {
+            Some(timing) => timing,
+            None => {
+                diagnostics.push(Diagnostic {
+                    line: start + 1,
+                    message: "no timing".to_string(),
+                });
+                continue;
+            }
+        };
+        let (cue_start, cue_end) = match parse_timing(block[timing]) {
+            Some(times) => times,
+            None => {
+                diagnostics.push(Diagnostic {
+                    line: start + timing + 1,
+                    message: format!("invalid timing {}", block[timing].trim()),
+                });
+                continue;
+            }
+        };
+        let texts = &block[timing + 1..];
+        let mut line = parse_cue_text(texts.first().copied().unwrap_or_default(), cue_start);
+        match join_texts(
+            texts
+                .iter()
+                .skip(1)
+                .map(|text| parse_cue_text(text, cue_start).text),
+        ) {
+            Some(rest) if is_translation(&line.text, &rest) => line.translation = Some(rest),
+            Some(_) => line = parse_cue_text(&texts.join(" "), cue_start),
+            None => {}
+        }
+        line.end = Some(cue_end);
+        lines.push(line);
+    }
+    Parsed {
+        out_of_order: out_of_order(lines.iter().map(|line| line.time)),
+        lyrics: Lyrics::new(merge_translations(lines)),
+        diagnostics,
+    }
+}
+
+/// Parses `00:00:12,000 --> 00:00:15,500`, ignoring the cue settings of
+/// WebVTT after it.
+fn parse_timing(timing: &str) -> Option<(Duration, Duration)> {
+    let (start, end) = timing.split_once("-->")?;
+    let end = end.split_whitespace().next()?;
+    Some((parse_timestamp(start)?, parse_timestamp(end)?))
+}
+
+/// Parses a timestamp like `01:02:03,456`, `02:03.456` or `1:02:03.45`.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let timestamp = timestamp.trim();
+    let (clock, fraction) = match timestamp.rfind(['.', ',']) {
+        Some(separator) => (&timestamp[..separator], Some(&timestamp[separator + 1..])),
+        None => (timestamp, None),
+    };
+    let parts = clock.split(':').collect::<Vec<_>>();
+    if parts.len() < 2 || parts.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0;
+    for part in parts.iter() {
+        seconds = seconds * 60 + part.trim().parse::<u64>().ok()?;
+    }
+    let millis = match fraction {
+        Some(fraction) if !fraction.is_empty() => {
+            let digits = &fraction[..fraction.len().min(3)];
+            let value: u64 = digits.parse().ok()?;
+            value * 10u64.pow(3 - digits.len() as u32)
+        }
+        _ => 0,
+    };
+    Some(Duration::from_millis(seconds * 1000 + millis))
+}
+
+/// Parses a line of cue text, dropping the markup we can't show. Voice spans
+/// set the agent and karaoke timestamps start new words.
+fn parse_cue_text(text: &str, start: Duration) -> Line {
+    let mut words: Vec<Word> = vec![];
+    let mut agent = None;
+    let mut timed = false;
+    let mut in_ruby_text = false;
+    let mut last = 0;
+    let push = |words: &mut Vec<Word>, text: &str, in_ruby_text: bool| {
+        // Ruby text annotates the text before it; there is no room for it.
+        if in_ruby_text {
+            return;
+        }
+        match words.last_mut() {
+            Some(word) => word.text.push_str(text),
+            None => words.push(Word::new(start, text)),
+        }
+    };
+    for captures in CUE_TAG.captures_iter(text) {
+        let tag = captures.get(0).unwrap();
+        push(&mut words, &text[last..tag.start()], in_ruby_text);
+        last = tag.end();
+        let closing = !captures[1].is_empty();
+        match &captures[2] {
+            // The name follows the classes, e.g. <v.loud Alice>.
+            "v" if !closing => {
+                agent = captures[3]
+                    .split_once(char::is_whitespace)
+                    .map(|(_, name)| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+            }
+            "rt" => in_ruby_text = !closing,
+            name if name.contains(':') => {
+                if let Some(time) = parse_timestamp(&tag.as_str()[1..tag.as_str().len() - 1]) {
+                    words.push(Word::new(time, ""));
+                    timed = true;
+                }
+            }
+            // Styles: <i>, <b>, <u>, <c>, <font>, <lang> and so on.
+            _ => {}
+        }
+    }
+    push(&mut words, &text[last..], in_ruby_text);
+    let mut line = if timed {
+        words.retain(|word| !word.text.is_empty() || word.time > start);
+        Line::with_words(start, words)
+    } else {
+        let text = words
+            .iter()
+            .map(|word| word.text.as_str())
+            .collect::<String>();
+        // SRT files made with ASS tools may keep their override blocks.
+        Line::new(start, OVERRIDE_BLOCK.replace_all(&text, "").trim())
+    };
+    line.agent = agent;
+    line
+}
+
+/// Whether `rest`, the lines of a cue after the first, is the translation of
+/// `first` rather than the rest of a long line: it is in another script.
+fn is_translation(first: &str, rest: &str) -> bool {
+    detect_script(first) != detect_script(rest)
+}
+
+fn join_texts(texts: impl Iterator<Item = String>) -> Option<String> {
+    Some(
+        texts
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+    .filter(|text| !text.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_wrapped_lines() {
+        let lyrics = parse_srt("1\n00:00:01,000 --> 00:00:03,000\nHello\n<i>world</i>\n").lyrics;
+        let line = &lyrics.lines()[0];
+        assert_eq!(line.text, "Hello world");
+        assert_eq!(line.translation, None);
+    }
+
+    #[test]
+    fn takes_lines_in_another_script_as_the_translation() {
+        let lyrics = parse_vtt("WEBVTT\n\n00:01.000 --> 00:03.000\n你好世界\nHello world\n").lyrics;
+        let line = &lyrics.lines()[0];
+        assert_eq!(line.text, "你好世界");
+        assert_eq!(line.translation.as_deref(), Some("Hello world"));
+    }
+
+    #[test]
+    fn splits_ass_text_at_line_breaks_only_for_translations() {
+        let lyrics = parse_ass(
+            "[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Hello\\Nworld
+Dialogue: 0,0:00:04.00,0:00:06.00,Default,,0,0,0,,こんにちは\\NHello
+",
+        )
+        .lyrics;
+        let lines = lyrics.lines();
+        assert_eq!(lines[0].text, "Hello world");
+        assert_eq!(lines[0].translation, None);
+        assert_eq!(lines[1].text, "こんにちは");
+        assert_eq!(lines[1].translation.as_deref(), Some("Hello"));
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+
+use super::id3v2::UNKNOWN_LANGUAGE;
+use super::replace_file;
+use crate::lyrics::Track;
+
+/// The Vorbis comments that may hold lyrics, from the most preferred.
+const VORBIS_LYRICS_FIELDS: [&str; 3] = ["SYNCEDLYRICS", "LYRICS", "UNSYNCEDLYRICS"];
+
+/// The metadata block of FLAC with the stream parameters, including its
+/// length.
+const FLAC_STREAMINFO: u8 = 0;
+
+/// The metadata block of FLAC with the Vorbis comments.
+const FLAC_VORBIS_COMMENT: u8 = 4;
+
+/// The largest metadata block, whose length has 24 bits.
+const MAX_BLOCK_LENGTH: usize = (1 << 24) - 1;
+
+/// The name of the encoder in the comments we add to files without any.
+const VENDOR: &str = "iLyrics";
+
+/// What the metadata blocks of a FLAC file tell.
+#[derive(Default)]
+struct Info {
+    /// `NAME=value` pairs, with names in upper case.
+    comments: Vec<(String, String)>,
+    duration: Option<Duration>,
+}
+
+pub fn read_lyrics(path: &Path) -> Result<Option<String>> {
+    let info = read_info(path)?;
+    Ok(VORBIS_LYRICS_FIELDS.iter().find_map(|field| {
+        info.comments
+            .iter()
+            .find(|(name, value)| name == field && !value.trim().is_empty())
+            .map(|(_, value)| value.clone())
+    }))
+}
+
+pub fn read_track(path: &Path) -> Result<Option<Track>> {
+    let info = read_info(path)?;
+    let comment = |field: &str| {
+        info.comments
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    Ok(Some(Track {
+        name: comment("TITLE"),
+        artist: comment("ARTIST"),
+        album: comment("ALBUM"),
+        album_artist: comment("ALBUMARTIST"),
+        duration: info.duration,
+        location: None,
+    }))
+}
+
+/// Replaces the lyrics comments of the FLAC file at `path` with
+/// `SYNCEDLYRICS` for `lrc`, if any, and `LYRICS` with `text` for players
+/// that can't sync. As with ID3 frames, only comments of unknown language are
+/// replaced; those with a language suffix of their own, e.g. `LYRICS-ENG`,
+/// are kept.
+pub fn write_lyrics(
+    path: &Path,
+    lrc: Option<&str>,
+    text: &str,
+    dry_run: bool,
+) -> Result<Vec<&'static str>> {
+    let data = fs::read(path)?;
+    let invalid = || anyhow!("Invalid FLAC file {}", path.display());
+    let mut rest = data.get(4..).ok_or_else(invalid)?;
+    let mut blocks = vec![];
+    loop {
+        let header = take(&mut rest, 4).ok_or_else(invalid)?;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        let block = take(&mut rest, length as usize).ok_or_else(invalid)?;
+        blocks.push((header[0] & 0x7f, block.to_vec()));
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    let (vendor, mut comments) = blocks
+        .iter()
+        .find(|(kind, _)| *kind == FLAC_VORBIS_COMMENT)
+        .map(|(_, block)| parse_comments(block).ok_or_else(invalid))
+        .transpose()?
+        .unwrap_or_else(|| (VENDOR.as_bytes().to_vec(), vec![]));
+    // Other comments are kept byte for byte.
+    comments.retain(|comment| {
+        let name = comment.split(|b| *b == b'=').next().unwrap_or_default();
+        !is_replaced(&String::from_utf8_lossy(name))
+    });
+    let mut fields = vec![];
+    if let Some(lrc) = lrc {
+        comments.push(format!("SYNCEDLYRICS={}", lrc).into_bytes());
+        fields.push("SYNCEDLYRICS");
+    }
+    comments.push(format!("LYRICS={}", text).into_bytes());
+    fields.push("LYRICS");
+    let block = encode_comments(&vendor, &comments);
+    if block.len() > MAX_BLOCK_LENGTH {
+        bail!("The lyrics are too long for {}", path.display());
+    }
+    match blocks
+        .iter()
+        .position(|(kind, _)| *kind == FLAC_VORBIS_COMMENT)
+    {
+        Some(index) => blocks[index].1 = block,
+        // STREAMINFO always comes first.
+        None => blocks.insert(1.min(blocks.len()), (FLAC_VORBIS_COMMENT, block)),
+    }
+    if !dry_run {
+        let mut output = b"fLaC".to_vec();
+        for (index, (kind, block)) in blocks.iter().enumerate() {
+            let last = if index + 1 == blocks.len() { 0x80 } else { 0 };
+            let length = (block.len() as u32).to_be_bytes();
+            output.extend_from_slice(&[kind | last, length[1], length[2], length[3]]);
+            output.extend_from_slice(block);
+        }
+        output.extend_from_slice(rest);
+        replace_file(path, &output)?;
+    }
+    Ok(fields)
+}
+
+/// Whether the comment `name` holds lyrics of unknown language, as we write
+/// them, without a language suffix (`LYRICS-ENG` or `LYRICS:ENG`) or with
+/// that of an unknown language.
+fn is_replaced(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    let (field, language) = match name.split_once(['-', ':']) {
+        Some((field, language)) => (field, language),
+        None => (name.as_str(), UNKNOWN_LANGUAGE),
+    };
+    VORBIS_LYRICS_FIELDS.contains(&field) && language == UNKNOWN_LANGUAGE
+}
+
+/// Reads the metadata blocks at the start of the FLAC file at `path`, up to
+/// the audio.
+fn read_info(path: &Path) -> Result<Info> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(4))?;
+    let mut info = Info::default();
+    loop {
+        let mut header = [0; 4];
+        file.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        match header[0] & 0x7f {
+            FLAC_STREAMINFO => info.duration = stream_duration(&read_block(&mut file, length)?),
+            FLAC_VORBIS_COMMENT => {
+                if let Some((_, comments)) = parse_comments(&read_block(&mut file, length)?) {
+                    info.comments = comments
+                        .iter()
+                        .filter_map(|comment| {
+                            let comment = String::from_utf8_lossy(comment);
+                            let (name, value) = comment.split_once('=')?;
+                            Some((name.to_ascii_uppercase(), value.to_string()))
+                        })
+                        .collect();
+                }
+            }
+            _ => {
+                file.seek(SeekFrom::Current(length as i64))?;
+            }
+        }
+        if is_last {
+            return Ok(info);
+        }
+    }
+}
+
+fn read_block(file: &mut File, length: u32) -> Result<Vec<u8>> {
+    let mut block = vec![0; length as usize];
+    file.read_exact(&mut block)?;
+    Ok(block)
+}
+
+/// Reads the length of the stream from its sample rate (20 bits from byte 10)
+/// and number of samples (36 bits from byte 13).
+fn stream_duration(block: &[u8]) -> Option<Duration> {
+    let bytes = block.get(10..18)?;
+    let packed = u64::from_be_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ]);
+    let sample_rate = packed >> 44;
+    let samples = packed & 0xf_ffff_ffff;
+    if sample_rate == 0 || samples == 0 {
+        return None;
+    }
+    Some(Duration::from_millis(samples * 1000 / sample_rate))
+}
+
+/// Splits a block of Vorbis comments, which are `NAME=value` pairs after the
+/// name of the encoder.
+fn parse_comments(mut block: &[u8]) -> Option<(Vec<u8>, Vec<Vec<u8>>)> {
+    let vendor_length = read_u32_le(&mut block)?;
+    let vendor = take(&mut block, vendor_length as usize)?.to_vec();
+    let count = read_u32_le(&mut block)?;
+    let mut comments = vec![];
+    for _ in 0..count {
+        let length = read_u32_le(&mut block)?;
+        comments.push(take(&mut block, length as usize)?.to_vec());
+    }
+    Some((vendor, comments))
+}
+
+fn encode_comments(vendor: &[u8], comments: &[Vec<u8>]) -> Vec<u8> {
+    let mut block = vec![];
+    block.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    block.extend_from_slice(vendor);
+    block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments.iter() {
+        block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        block.extend_from_slice(comment);
+    }
+    block
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Option<&'a [u8]> {
+    if data.len() < length {
+        return None;
+    }
+    let (taken, rest) = data.split_at(length);
+    *data = rest;
+    Some(taken)
+}
+
+fn read_u32_le(data: &mut &[u8]) -> Option<u32> {
+    let bytes = take(data, 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
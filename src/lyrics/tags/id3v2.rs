@@ -0,0 +1,175 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use id3::frame::Content;
+use id3::frame::Lyrics as UnsynchronisedLyrics;
+use id3::frame::SynchronisedLyrics;
+use id3::frame::SynchronisedLyricsType;
+use id3::frame::TimestampFormat;
+use id3::Frame;
+use id3::Tag;
+use id3::TagLike;
+
+use crate::lyrics::export;
+use crate::lyrics::format::Format;
+use crate::lyrics::Line;
+use crate::lyrics::Lyrics;
+use crate::lyrics::Track;
+use crate::lyrics::Word;
+
+/// The language of the lyrics frames we write, which we don't know.
+pub const UNKNOWN_LANGUAGE: &str = "XXX";
+
+pub fn read_lyrics(path: &Path) -> Result<Option<String>> {
+    let tag = match id3::no_tag_ok(Tag::read_from_path(path))? {
+        Some(tag) => tag,
+        None => return Ok(None),
+    };
+    // MPEG frames can't be turned into times without decoding the audio.
+    let synchronised = tag.synchronised_lyrics().find(|lyrics| {
+        lyrics.timestamp_format == TimestampFormat::Ms
+            && lyrics.content_type == SynchronisedLyricsType::Lyrics
+            && !lyrics.content.is_empty()
+    });
+    if let Some(lyrics) = synchronised {
+        return Ok(Some(export(
+            &synchronised_lines(&lyrics.content),
+            Format::Lrc,
+            0,
+        )?));
+    }
+    let unsynchronised = tag
+        .lyrics()
+        .map(|lyrics| lyrics.text.clone())
+        .find(|text| !text.trim().is_empty());
+    Ok(unsynchronised)
+}
+
+pub fn read_track(path: &Path) -> Result<Option<Track>> {
+    let tag = match id3::no_tag_ok(Tag::read_from_path(path))? {
+        Some(tag) => tag,
+        None => return Ok(None),
+    };
+    let text = |value: Option<&str>| value.unwrap_or_default().trim().to_string();
+    Ok(Some(Track {
+        name: text(tag.title()),
+        artist: text(tag.artist()),
+        album: text(tag.album()),
+        album_artist: text(tag.album_artist()),
+        duration: tag
+            .duration()
+            .map(|millis| Duration::from_millis(millis as u64)),
+        location: None,
+    }))
+}
+
+/// Replaces the lyrics frames of the MP3 file at `path` with a SYLT frame
+/// for `synced` lyrics, if any, and a USLT frame with `text` for players that
+/// can't sync. Only frames of unknown language without a description, as
+/// written here, are replaced.
+pub fn write_lyrics(
+    path: &Path,
+    synced: Option<&Lyrics>,
+    text: &str,
+    dry_run: bool,
+) -> Result<Vec<&'static str>> {
+    let mut tag = id3::no_tag_ok(Tag::read_from_path(path))?.unwrap_or_default();
+    // Lyrics in other languages or with other descriptions are kept, after
+    // ours so that ours are read first.
+    let others = tag
+        .remove("SYLT")
+        .into_iter()
+        .chain(tag.remove("USLT"))
+        .filter(|frame| !is_ours(frame))
+        .collect::<Vec<_>>();
+    let mut fields = vec![];
+    if let Some(lyrics) = synced {
+        tag.add_frame(SynchronisedLyrics {
+            lang: UNKNOWN_LANGUAGE.to_string(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: synchronised_content(lyrics),
+        });
+        fields.push("SYLT");
+    }
+    fields.push("USLT");
+    tag.add_frame(UnsynchronisedLyrics {
+        lang: UNKNOWN_LANGUAGE.to_string(),
+        description: String::new(),
+        text: text.to_string(),
+    });
+    for frame in others {
+        tag.add_frame(frame);
+    }
+    if !dry_run {
+        // Keeps the version of an existing tag; new tags are ID3v2.4.
+        tag.write_to_path(path, tag.version())?;
+    }
+    Ok(fields)
+}
+
+/// Whether `frame` holds lyrics as [`write_lyrics`] writes them, which it
+/// replaces.
+fn is_ours(frame: &Frame) -> bool {
+    match frame.content() {
+        Content::Lyrics(lyrics) => lyrics.lang == UNKNOWN_LANGUAGE && lyrics.description.is_empty(),
+        Content::SynchronisedLyrics(lyrics) => {
+            lyrics.lang == UNKNOWN_LANGUAGE && lyrics.description.is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// Turns the timed texts of a SYLT frame into lines.
+///
+/// Taggers either give each line a text, or each syllable, starting new lines
+/// with a line break.
+fn synchronised_lines(content: &[(u32, String)]) -> Lyrics {
+    let is_break = |c: char| c == '\n' || c == '\r';
+    let by_syllable = content.iter().any(|(_, text)| text.contains(is_break));
+    let mut lines = vec![];
+    let mut words: Vec<Word> = vec![];
+    let mut flush = |words: &mut Vec<Word>| {
+        match words.len() {
+            0 => {}
+            1 => lines.push(Line::new(words[0].time, words[0].text.trim())),
+            _ => lines.push(Line::with_words(words[0].time, words.clone())),
+        }
+        words.clear();
+    };
+    for (time, text) in content.iter() {
+        if !by_syllable || text.starts_with(is_break) {
+            flush(&mut words);
+        }
+        let time = Duration::from_millis(*time as u64);
+        words.push(Word::new(time, text.trim_matches(is_break)));
+        if by_syllable && text.ends_with(is_break) {
+            flush(&mut words);
+        }
+    }
+    flush(&mut words);
+    Lyrics::new(lines)
+}
+
+/// The inverse of [`synchronised_lines`]: words become syllables, and every
+/// line but the first starts with a line break.
+fn synchronised_content(lyrics: &Lyrics) -> Vec<(u32, String)> {
+    let millis = |time: Duration| time.as_millis().min(u32::MAX as u128) as u32;
+    let mut content = vec![];
+    for line in lyrics.lines() {
+        let mut prefix = if content.is_empty() { "" } else { "\n" };
+        if line.words.is_empty() {
+            content.push((millis(line.time), format!("{}{}", prefix, line.text)));
+            continue;
+        }
+        // A word without text only ends the one before it, which SYLT can't
+        // say.
+        for word in line.words.iter().filter(|word| !word.text.is_empty()) {
+            content.push((millis(word.time), format!("{}{}", prefix, word.text)));
+            prefix = "";
+        }
+    }
+    content
+}
@@ -0,0 +1,307 @@
+mod flac;
+mod id3v2;
+mod mp4;
+
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use super::export;
+use super::format;
+use super::format::Format;
+use super::Lyrics;
+use super::Track;
+
+/// The kinds of tags that lyrics can be written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Id3,
+    Flac,
+    Mp4,
+}
+
+/// Reads the lyrics embedded in the audio file at `path`, as a document in
+/// one of the formats of [`Format`].
+///
+/// Supports synchronized (SYLT) and unsynchronized (USLT) lyrics in ID3v2
+/// tags, the `SYNCEDLYRICS` and `LYRICS` comments of FLAC, and the `©lyr`
+/// atom of MP4. Synchronized lyrics come first; they are written as LRC.
+/// Returns `None` if the file has no lyrics. See `fixtures/embedded` for
+/// small samples of each.
+pub fn read_lyrics(path: &Path) -> Result<Option<String>> {
+    let lyrics = match detect(path)? {
+        Some(Container::Flac) => flac::read_lyrics(path)?,
+        Some(Container::Mp4) => mp4::read_lyrics(path)?,
+        // MP3, or WAV and AIFF with an ID3 chunk.
+        _ => id3v2::read_lyrics(path)?,
+    };
+    Ok(lyrics.filter(|lyrics| !lyrics.trim().is_empty()))
+}
+
+/// Reads which track the audio file at `path` is from its tags, so that its
+/// lyrics can be looked up. Returns `None` if it has no title or artist.
+pub fn read_track(path: &Path) -> Result<Option<Track>> {
+    let track = match detect(path)? {
+        Some(Container::Flac) => flac::read_track(path)?,
+        Some(Container::Mp4) => mp4::read_track(path)?,
+        _ => id3v2::read_track(path)?,
+    };
+    Ok(track
+        .filter(|track| !track.name.is_empty() && !track.artist.is_empty())
+        .map(|track| Track {
+            location: Some(path.to_path_buf()),
+            ..track
+        }))
+}
+
+/// Embeds `lyrics` into the tags of the audio file at `path`, so that other
+/// players and devices show them too, and returns the fields written.
+///
+/// Times are moved by the `[offset:]` of the document and by `user_offset`,
+/// as in [`export`]. MP3 files get SYLT and USLT frames, FLAC files the
+/// `SYNCEDLYRICS` and `LYRICS` comments, and MP4 files the `©lyr` atom, as
+/// LRC. Lyrics with estimated times are written as plain text only. Earlier
+/// lyrics are replaced, except ID3 frames in a language or with a description
+/// of their own and Vorbis comments in a language, and all other tags are
+/// kept. With `dry_run`, the file is left
+/// untouched.
+pub fn write_lyrics(
+    path: &Path,
+    lyrics: &Lyrics,
+    user_offset: i64,
+    dry_run: bool,
+) -> Result<Vec<&'static str>> {
+    let text = export(lyrics, Format::Plain, 0)?;
+    // Made-up times are worse than none.
+    let lrc = if lyrics.metadata().estimated {
+        None
+    } else {
+        Some(export(lyrics, Format::Lrc, user_offset)?)
+    };
+    match detect(path)? {
+        Some(Container::Id3) => {
+            // The offsets are applied in the LRC.
+            let synced = lrc.as_deref().map(|lrc| format::parse(lrc).lyrics);
+            id3v2::write_lyrics(path, synced.as_ref(), &text, dry_run)
+        }
+        Some(Container::Flac) => flac::write_lyrics(path, lrc.as_deref(), &text, dry_run),
+        Some(Container::Mp4) => mp4::write_lyrics(path, lrc.as_deref().unwrap_or(&text), dry_run),
+        None => bail!("Can't write lyrics to {}", path.display()),
+    }
+}
+
+/// Tells the kind of the audio file at `path` by its first bytes. MP3 files
+/// without tags are told by their extension.
+fn detect(path: &Path) -> Result<Option<Container>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 8];
+    if file.read_exact(&mut magic).is_err() {
+        return Ok(None);
+    }
+    let is_mp3 = path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("mp3"))
+        .unwrap_or(false);
+    Ok(if &magic[..4] == b"fLaC" {
+        Some(Container::Flac)
+    } else if &magic[4..] == b"ftyp" {
+        Some(Container::Mp4)
+    } else if &magic[..3] == b"ID3" || is_mp3 {
+        Some(Container::Id3)
+    } else {
+        None
+    })
+}
+
+/// Replaces the file at `path` with `data`, through a temporary file next to
+/// it so that a failed write doesn't leave it truncated.
+fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".ilyrics-tmp");
+    fs::write(&temporary, data)?;
+    if let Err(e) = fs::rename(&temporary, path) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use id3::Tag;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::lyrics::Line;
+    use crate::lyrics::Metadata;
+
+    /// The samples in `fixtures/embedded`, one of each kind of tag.
+    const FIXTURES: [&str; 5] = [
+        "sylt.mp3",
+        "uslt.mp3",
+        "lyrics.flac",
+        "lyrics.m4a",
+        "offsets.m4a",
+    ];
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join("embedded")
+            .join(name)
+    }
+
+    /// Copies the fixture `name` into `directory`, to be written to.
+    fn copy_fixture(directory: &TempDir, name: &str) -> PathBuf {
+        let path = directory.path().join(name);
+        fs::copy(fixture(name), &path).unwrap();
+        path
+    }
+
+    fn new_lyrics() -> Lyrics {
+        Lyrics::new(vec![Line::new(Duration::from_secs(2), "New line")])
+    }
+
+    /// Checks that `content` holds the lines of the fixtures.
+    fn assert_fixture_lines(content: &str, name: &str) {
+        let lyrics = format::parse(content).lyrics;
+        let lines = lyrics
+            .lines()
+            .iter()
+            .filter(|line| !line.text.is_empty())
+            .map(|line| (line.time, line.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                (Duration::from_secs(1), "Hello world"),
+                (Duration::from_secs(3), "Second line"),
+            ],
+            "{}",
+            name
+        );
+    }
+
+    #[test]
+    fn reads_fixtures() {
+        for name in FIXTURES.iter() {
+            let content = read_lyrics(&fixture(name))
+                .unwrap()
+                .unwrap_or_else(|| panic!("No lyrics in {}", name));
+            assert_fixture_lines(&content, name);
+        }
+    }
+
+    #[test]
+    fn writes_fixtures() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in FIXTURES.iter() {
+            let path = copy_fixture(&directory, name);
+            let track = read_track(&path).unwrap();
+            write_lyrics(&path, &new_lyrics(), 500, false).unwrap();
+            let content = read_lyrics(&path).unwrap().unwrap();
+            let lyrics = format::parse(&content).lyrics;
+            assert_eq!(
+                lyrics.lines(),
+                [Line::new(Duration::from_millis(1500), "New line")],
+                "{}",
+                name
+            );
+            assert_eq!(read_track(&path).unwrap(), track, "{}", name);
+        }
+    }
+
+    #[test]
+    fn writes_estimated_lyrics_as_text() {
+        let directory = tempfile::tempdir().unwrap();
+        let lyrics = new_lyrics().with_metadata(Metadata {
+            estimated: true,
+            ..Default::default()
+        });
+        for name in FIXTURES.iter() {
+            let path = copy_fixture(&directory, name);
+            let fields = write_lyrics(&path, &lyrics, 0, false).unwrap();
+            assert!(!fields.contains(&"SYLT") && !fields.contains(&"SYNCEDLYRICS"));
+            if !name.ends_with(".mp3") {
+                assert_eq!(read_lyrics(&path).unwrap().unwrap(), "New line", "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_files_untouched_in_dry_runs() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in FIXTURES.iter() {
+            let path = copy_fixture(&directory, name);
+            let before = fs::read(&path).unwrap();
+            let fields = write_lyrics(&path, &new_lyrics(), 0, true).unwrap();
+            assert!(!fields.is_empty(), "{}", name);
+            assert_eq!(fs::read(&path).unwrap(), before, "{}", name);
+        }
+    }
+
+    #[test]
+    fn keeps_other_id3_lyrics() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = copy_fixture(&directory, "sylt.mp3");
+        // Twice, so that the frames written first are replaced.
+        write_lyrics(&path, &new_lyrics(), 0, false).unwrap();
+        write_lyrics(&path, &new_lyrics(), 0, false).unwrap();
+        let tag = Tag::read_from_path(&path).unwrap();
+        let mut languages = tag
+            .lyrics()
+            .map(|lyrics| lyrics.lang.as_str())
+            .chain(tag.synchronised_lyrics().map(|lyrics| lyrics.lang.as_str()))
+            .collect::<Vec<_>>();
+        languages.sort_unstable();
+        assert_eq!(languages, ["XXX", "XXX", "eng", "eng"]);
+        let original = tag.lyrics().find(|lyrics| lyrics.lang == "eng").unwrap();
+        assert_eq!(original.text, "Hello world\nSecond line");
+    }
+
+    #[test]
+    fn keeps_other_flac_lyrics() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = copy_fixture(&directory, "languages.flac");
+        // Twice, so that the comments written first are replaced.
+        write_lyrics(&path, &new_lyrics(), 0, false).unwrap();
+        write_lyrics(&path, &new_lyrics(), 0, false).unwrap();
+        let data = fs::read(&path).unwrap();
+        let count = |comment: &[u8]| {
+            data.windows(comment.len())
+                .filter(|window| *window == comment)
+                .count()
+        };
+        assert_eq!(count(b"LYRICS-ENG=Hello world\nSecond line"), 1);
+        assert_eq!(count(b"LYRICS=New line"), 1);
+        assert_eq!(count(b"SYNCEDLYRICS=[00:02.00]New line"), 1);
+        assert_eq!(count(b"Old line"), 0);
+        assert_eq!(count(b"=Hello world\nSecond line"), 1);
+    }
+
+    /// The first chunk offset in the `stco` atom of an MP4 file.
+    fn first_chunk_offset(data: &[u8]) -> usize {
+        let start = data.windows(4).position(|name| name == b"stco").unwrap();
+        let offset = &data[start + 12..start + 16];
+        u32::from_be_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize
+    }
+
+    #[test]
+    fn moves_mp4_chunk_offsets_along_with_the_audio() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = copy_fixture(&directory, "offsets.m4a");
+        let before = fs::read(&path).unwrap();
+        let audio = &before[first_chunk_offset(&before)..];
+        write_lyrics(&path, &new_lyrics(), 0, false).unwrap();
+        let after = fs::read(&path).unwrap();
+        assert_ne!(after.len(), before.len());
+        assert_eq!(&after[first_chunk_offset(&after)..], audio);
+    }
+}
@@ -0,0 +1,290 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use super::replace_file;
+use crate::lyrics::Track;
+
+/// The path of the items in the metadata of MP4 files.
+const MP4_ITEMS_PATH: [&[u8; 4]; 4] = [b"moov", b"udta", b"meta", b"ilst"];
+
+/// The atoms with other atoms in them, on the way to the items and to the
+/// chunk offsets.
+const CONTAINERS: [&[u8; 4]; 8] = [
+    b"moov", b"trak", b"mdia", b"minf", b"stbl", b"udta", b"meta", b"ilst",
+];
+
+const LYRICS: &[u8; 4] = b"\xa9lyr";
+
+/// The handler of iTunes metadata, which players look for before the items.
+const METADATA_HANDLER: &[u8] = b"\0\0\0\0\0\0\0\0mdirappl\0\0\0\0\0\0\0\0\0";
+
+/// The type of data atoms with UTF-8 text.
+const UTF8: u32 = 1;
+
+/// An atom of an MP4 file read into memory.
+struct Atom {
+    name: [u8; 4],
+    /// The content of a leaf, or what comes before the children of a
+    /// container, like the version and flags of meta.
+    data: Vec<u8>,
+    children: Vec<Atom>,
+}
+
+pub fn read_lyrics(path: &Path) -> Result<Option<String>> {
+    read_item(&mut File::open(path)?, LYRICS)
+}
+
+pub fn read_track(path: &Path) -> Result<Option<Track>> {
+    let mut file = File::open(path)?;
+    let mut item = |name: &[u8; 4]| -> Result<String> {
+        Ok(read_item(&mut file, name)?
+            .unwrap_or_default()
+            .trim()
+            .to_string())
+    };
+    Ok(Some(Track {
+        name: item(b"\xa9nam")?,
+        artist: item(b"\xa9ART")?,
+        album: item(b"\xa9alb")?,
+        album_artist: item(b"aART")?,
+        duration: None,
+        location: None,
+    }))
+}
+
+/// Replaces the `©lyr` atom of the MP4 file at `path` with `lyrics`, LRC or
+/// plain text, creating the metadata atoms it goes in if needed.
+pub fn write_lyrics(path: &Path, lyrics: &str, dry_run: bool) -> Result<Vec<&'static str>> {
+    let data = fs::read(path)?;
+    let invalid = || anyhow!("Invalid MP4 file {}", path.display());
+    let (mut moov, moov_start, moov_end) = {
+        let mut start = 0;
+        loop {
+            let (name, header_length, size) = atom_header(&data[start..])
+                .ok_or_else(|| anyhow!("No moov atom in {}", path.display()))?;
+            if &name == b"moov" {
+                let atom = parse_atom(name, &data[start + header_length..start + size])
+                    .ok_or_else(invalid)?;
+                break (atom, start, start + size);
+            }
+            start += size;
+        }
+    };
+    let udta = child(&mut moov, b"udta", || leaf(b"udta", vec![]));
+    let meta = child(udta, b"meta", || Atom {
+        name: *b"meta",
+        data: vec![0; 4],
+        children: vec![leaf(b"hdlr", METADATA_HANDLER.to_vec())],
+    });
+    let ilst = child(meta, b"ilst", || leaf(b"ilst", vec![]));
+    let mut item = vec![];
+    write_atom(
+        &leaf(
+            b"data",
+            [&UTF8.to_be_bytes()[..], &[0; 4], lyrics.as_bytes()].concat(),
+        ),
+        &mut item,
+    );
+    *child(ilst, LYRICS, || leaf(LYRICS, vec![])) = leaf(LYRICS, item);
+
+    let delta = encoded_size(&moov) as i64 - (moov_end - moov_start) as i64;
+    // The audio moves along if it comes after the metadata.
+    let mut rest = &data[moov_end..];
+    while let Some((name, _, size)) = atom_header(rest) {
+        if &name == b"mdat" {
+            shift_chunk_offsets(&mut moov, delta).ok_or_else(invalid)?;
+            break;
+        }
+        rest = &rest[size..];
+    }
+    if !dry_run {
+        let mut output = data[..moov_start].to_vec();
+        write_atom(&moov, &mut output);
+        output.extend_from_slice(&data[moov_end..]);
+        replace_file(path, &output)?;
+    }
+    Ok(vec!["\u{a9}lyr"])
+}
+
+/// Reads the text of the item `name` in the metadata.
+fn read_item(file: &mut File, name: &[u8; 4]) -> Result<Option<String>> {
+    let mut start = 0;
+    let mut end = file.metadata()?.len();
+    for name in MP4_ITEMS_PATH.iter().copied().chain([name, b"data"]) {
+        match find_atom(file, start, end, name)? {
+            Some((atom_start, atom_end)) => {
+                start = atom_start;
+                end = atom_end;
+            }
+            None => return Ok(None),
+        }
+    }
+    // The data atom starts with its type and locale.
+    start += 8;
+    if start > end {
+        return Ok(None);
+    }
+    let mut data = vec![0; (end - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut data)?;
+    Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+}
+
+/// Finds the atom `name` between `start` and `end`, and returns where its
+/// content starts and ends.
+fn find_atom(
+    file: &mut File,
+    mut start: u64,
+    end: u64,
+    name: &[u8; 4],
+) -> Result<Option<(u64, u64)>> {
+    while start + 8 <= end {
+        file.seek(SeekFrom::Start(start))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let mut content_start = start + 8;
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            // Up to the end of the file.
+            0 => end - start,
+            // A 64-bit size follows.
+            1 => {
+                let mut size = [0; 8];
+                file.read_exact(&mut size)?;
+                content_start += 8;
+                u64::from_be_bytes(size)
+            }
+            size => size as u64,
+        };
+        if size < content_start - start || start + size > end {
+            break;
+        }
+        if &header[4..] == name {
+            // The meta atom of MP4 has a version and flags before its
+            // children, unlike that of QuickTime, whose first child is hdlr.
+            if name == b"meta" {
+                let mut next = [0; 8];
+                file.read_exact(&mut next)?;
+                if &next[4..] != b"hdlr" {
+                    content_start += 4;
+                }
+            }
+            return Ok(Some((content_start, start + size)));
+        }
+        start += size;
+    }
+    Ok(None)
+}
+
+/// Reads the name, the length of the header and the size of the atom that
+/// `data` starts with.
+fn atom_header(data: &[u8]) -> Option<([u8; 4], usize, usize)> {
+    let header = data.get(..8)?;
+    let name = [header[4], header[5], header[6], header[7]];
+    let (header_length, size) =
+        match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (8, data.len()),
+            1 => {
+                let size = data.get(8..16)?;
+                let size = u64::from_be_bytes([
+                    size[0], size[1], size[2], size[3], size[4], size[5], size[6], size[7],
+                ]);
+                (16, size as usize)
+            }
+            size => (8, size as usize),
+        };
+    if size < header_length || size > data.len() {
+        return None;
+    }
+    Some((name, header_length, size))
+}
+
+fn parse_atom(name: [u8; 4], content: &[u8]) -> Option<Atom> {
+    if !CONTAINERS.contains(&&name) {
+        return Some(leaf(&name, content.to_vec()));
+    }
+    let prefix = if &name == b"meta" && content.get(4..8) != Some(&b"hdlr"[..]) {
+        4
+    } else {
+        0
+    };
+    let mut rest = content.get(prefix..)?;
+    let mut children = vec![];
+    while !rest.is_empty() {
+        let (name, header_length, size) = atom_header(rest)?;
+        children.push(parse_atom(name, &rest[header_length..size])?);
+        rest = &rest[size..];
+    }
+    Some(Atom {
+        name,
+        data: content[..prefix].to_vec(),
+        children,
+    })
+}
+
+fn leaf(name: &[u8; 4], data: Vec<u8>) -> Atom {
+    Atom {
+        name: *name,
+        data,
+        children: vec![],
+    }
+}
+
+/// The child `name` of `atom`, added with `new` if there is none.
+fn child<'a>(atom: &'a mut Atom, name: &[u8; 4], new: impl FnOnce() -> Atom) -> &'a mut Atom {
+    let index = match atom.children.iter().position(|child| &child.name == name) {
+        Some(index) => index,
+        None => {
+            atom.children.push(new());
+            atom.children.len() - 1
+        }
+    };
+    &mut atom.children[index]
+}
+
+fn encoded_size(atom: &Atom) -> usize {
+    8 + atom.data.len() + atom.children.iter().map(encoded_size).sum::<usize>()
+}
+
+fn write_atom(atom: &Atom, output: &mut Vec<u8>) {
+    output.extend_from_slice(&(encoded_size(atom) as u32).to_be_bytes());
+    output.extend_from_slice(&atom.name);
+    output.extend_from_slice(&atom.data);
+    for child in atom.children.iter() {
+        write_atom(child, output);
+    }
+}
+
+/// Moves the chunk offsets of every track by `delta` bytes, as the audio
+/// they point into moves. Returns `None` if an offset would overflow.
+fn shift_chunk_offsets(atom: &mut Atom, delta: i64) -> Option<()> {
+    for child in atom.children.iter_mut() {
+        shift_chunk_offsets(child, delta)?;
+    }
+    let width = match &atom.name {
+        b"stco" => 4,
+        b"co64" => 8,
+        _ => return Some(()),
+    };
+    // After the version, the flags and the number of entries.
+    for entry in atom.data.get_mut(8..)?.chunks_exact_mut(width) {
+        if width == 4 {
+            let offset = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as i64;
+            let offset = u32::try_from(offset + delta).ok()?;
+            entry.copy_from_slice(&offset.to_be_bytes());
+        } else {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(entry);
+            let offset = u64::try_from(u64::from_be_bytes(bytes) as i64 + delta).ok()?;
+            entry.copy_from_slice(&offset.to_be_bytes());
+        }
+    }
+    Some(())
+}
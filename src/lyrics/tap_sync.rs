@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use super::shift;
+use super::Line;
+use super::Lyrics;
+use super::Metadata;
+use super::Track;
+
+/// Lyrics being synced by hand, with a tap as each line starts being sung.
+///
+/// The lines are those of other lyrics, usually plain text, stripped of
+/// their times; stanza breaks are left out. Once every line is tapped, the
+/// times can still be fine-adjusted.
+pub struct TapSync {
+    lines: Vec<Line>,
+    /// How many lines have been tapped, from the first.
+    tapped: usize,
+    metadata: Metadata,
+}
+
+impl TapSync {
+    /// Starts syncing the lines of `lyrics`, sung in `track`. Returns `None`
+    /// if there is nothing to sync.
+    pub fn new(lyrics: &Lyrics, track: &Track) -> Option<Self> {
+        let lines = lyrics
+            .lines()
+            .iter()
+            .filter(|line| !line.text.is_empty())
+            .map(|line| Line {
+                end: None,
+                words: vec![],
+                background: vec![],
+                ..line.clone()
+            })
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return None;
+        }
+        let text = |value: &str| Some(value.to_string()).filter(|value| !value.is_empty());
+        Some(Self {
+            lines,
+            tapped: 0,
+            metadata: Metadata {
+                title: text(&track.name),
+                artist: text(&track.artist),
+                album: text(&track.album),
+                ..Default::default()
+            },
+        })
+    }
+
+    /// The line to tap next, if any are left.
+    pub fn next_line(&self) -> Option<&Line> {
+        self.lines.get(self.tapped)
+    }
+
+    /// The line tapped last, if any.
+    pub fn last_line(&self) -> Option<&Line> {
+        self.tapped.checked_sub(1).map(|index| &self.lines[index])
+    }
+
+    /// How many lines have been tapped, and how many there are.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.tapped, self.lines.len())
+    }
+
+    /// Starts the next line at `time`, or along with the line before it if
+    /// that is later.
+    pub fn tap(&mut self, time: Duration) {
+        let earliest = self.last_line().map(|line| line.time).unwrap_or_default();
+        if let Some(line) = self.lines.get_mut(self.tapped) {
+            line.time = time.max(earliest);
+            self.tapped += 1;
+        }
+    }
+
+    /// Takes back the last tap. Returns whether there was one.
+    pub fn undo(&mut self) -> bool {
+        if self.tapped == 0 {
+            return false;
+        }
+        self.tapped -= 1;
+        true
+    }
+
+    /// Moves the tapped line being sung at `time` by `millis`, positive values
+    /// making it later, but not past the lines around it. Returns the new
+    /// time of the line.
+    pub fn nudge(&mut self, time: Duration, millis: i64) -> Option<Duration> {
+        let tapped = &mut self.lines[..self.tapped];
+        let index = tapped
+            .partition_point(|line| line.time <= time)
+            .checked_sub(1)?;
+        let earliest = index
+            .checked_sub(1)
+            .map(|previous| tapped[previous].time)
+            .unwrap_or_default();
+        let latest = tapped.get(index + 1).map(|next| next.time);
+        let mut moved = shift(tapped[index].time, millis).max(earliest);
+        if let Some(latest) = latest {
+            moved = moved.min(latest);
+        }
+        tapped[index].time = moved;
+        Some(moved)
+    }
+
+    /// The lines tapped so far, timed as in the track.
+    pub fn lyrics(&self) -> Lyrics {
+        Lyrics::new(self.lines[..self.tapped].to_vec()).with_metadata(self.metadata.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    /// Three lines to sync, with a stanza break after the first.
+    fn tap_sync() -> TapSync {
+        let lyrics = Lyrics::new(vec![
+            Line::new(secs(0), "First"),
+            Line::new(secs(1), ""),
+            Line::new(secs(2), "Second"),
+            Line::new(secs(3), "Third"),
+        ]);
+        let track = Track {
+            name: "Hello".to_string(),
+            artist: "iLyrics".to_string(),
+            album: String::new(),
+            album_artist: String::new(),
+            duration: None,
+            location: None,
+        };
+        TapSync::new(&lyrics, &track).unwrap()
+    }
+
+    fn times(tap_sync: &TapSync) -> Vec<Duration> {
+        tap_sync
+            .lyrics()
+            .lines()
+            .iter()
+            .map(|line| line.time)
+            .collect()
+    }
+
+    #[test]
+    fn clamps_taps_to_after_the_previous_line() {
+        let mut tap_sync = tap_sync();
+        tap_sync.tap(secs(10));
+        tap_sync.tap(secs(5));
+        assert_eq!(times(&tap_sync), [secs(10), secs(10)]);
+    }
+
+    #[test]
+    fn taps_again_after_undo() {
+        let mut tap_sync = tap_sync();
+        assert!(!tap_sync.undo());
+        tap_sync.tap(secs(10));
+        tap_sync.tap(secs(20));
+        assert!(tap_sync.undo());
+        assert_eq!(tap_sync.progress(), (1, 3));
+        assert_eq!(tap_sync.next_line().unwrap().text, "Second");
+        tap_sync.tap(secs(15));
+        assert_eq!(times(&tap_sync), [secs(10), secs(15)]);
+        assert_eq!(tap_sync.last_line().unwrap().text, "Second");
+    }
+
+    #[test]
+    fn nudges_within_the_lines_around() {
+        let mut tap_sync = tap_sync();
+        for time in [10, 20, 30].iter() {
+            tap_sync.tap(secs(*time));
+        }
+        assert_eq!(
+            tap_sync.nudge(secs(25), 500),
+            Some(Duration::from_millis(20_500))
+        );
+        assert_eq!(tap_sync.nudge(secs(25), 60_000), Some(secs(30)));
+        assert_eq!(tap_sync.nudge(secs(35), -60_000), Some(secs(30)));
+        assert_eq!(tap_sync.nudge(secs(15), -60_000), Some(secs(0)));
+        assert_eq!(tap_sync.nudge(secs(5), -500), Some(secs(0)));
+        assert_eq!(times(&tap_sync), [secs(0), secs(30), secs(30)]);
+    }
+
+    #[test]
+    fn completes_after_the_last_line_only() {
+        let mut tap_sync = tap_sync();
+        assert_eq!(tap_sync.progress(), (0, 3));
+        tap_sync.tap(secs(10));
+        tap_sync.tap(secs(20));
+        assert_eq!(tap_sync.progress(), (2, 3));
+        assert!(tap_sync.next_line().is_some());
+        tap_sync.tap(secs(30));
+        assert_eq!(tap_sync.progress(), (3, 3));
+        assert!(tap_sync.next_line().is_none());
+        // Taps past the last line are ignored.
+        tap_sync.tap(secs(40));
+        assert_eq!(times(&tap_sync), [secs(10), secs(20), secs(30)]);
+    }
+}
@@ -0,0 +1,375 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A timed line of lyrics.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Line {
+    pub time: Duration,
+    /// When the line is over, if the document says so. Otherwise the line
+    /// lasts until the next one.
+    pub end: Option<Duration>,
+    pub text: String,
+    /// The timings of the words of [`Line::text`] in enhanced LRC or TTML,
+    /// empty otherwise. The texts of the words make up the text of the line.
+    pub words: Vec<Word>,
+    /// The singer of the line in duets, e.g. `v1` or a name.
+    pub agent: Option<String>,
+    /// The timed words of the background vocals sung along with the line.
+    pub background: Vec<Word>,
+    /// The text in another language, shown along with the original.
+    pub translation: Option<String>,
+    /// The text spelled out in Latin letters, for those who can't read it.
+    pub romanization: Option<String>,
+}
+
+/// A timed word, or syllable, of a line.
+///
+/// A word with empty text marks where the previous word ends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Word {
+    pub time: Duration,
+    pub text: String,
+}
+
+impl Word {
+    pub fn new(time: Duration, text: &str) -> Self {
+        Self {
+            time,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// How far a line with word timings has been sung.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// The length of the text sung in full, in bytes.
+    pub sung: usize,
+    /// The length of the text up to the end of the word being sung, in bytes.
+    pub singing: usize,
+    /// How much of the word being sung has been sung, from 0 to 1.
+    pub fraction: f64,
+}
+
+impl Line {
+    pub fn new(time: Duration, text: &str) -> Self {
+        Self {
+            time,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// A line made up of `words`, trimming the whitespace around them.
+    pub fn with_words(time: Duration, words: Vec<Word>) -> Self {
+        let mut line = Self::new(time, "");
+        line.set_words(words);
+        line
+    }
+
+    /// Replaces the words of the line, and its text with theirs.
+    pub fn set_words(&mut self, mut words: Vec<Word>) {
+        if let Some(first) = words.first_mut() {
+            first.text = first.text.trim_start().to_string();
+        }
+        if let Some(last) = words.iter_mut().rev().find(|word| !word.text.is_empty()) {
+            last.text = last.text.trim_end().to_string();
+        }
+        self.text = words.iter().map(|word| word.text.as_str()).collect();
+        self.words = words;
+    }
+
+    /// Applies `f` to each text of the line: the text or its words, the
+    /// background vocals, the translation and the romanization.
+    pub fn map_text(&self, mut f: impl FnMut(&str) -> String) -> Self {
+        let mut map_words = |words: &[Word]| {
+            words
+                .iter()
+                .map(|word| Word::new(word.time, &f(&word.text)))
+                .collect::<Vec<_>>()
+        };
+        let mut line = if self.words.is_empty() {
+            Self::new(self.time, "")
+        } else {
+            Self::with_words(self.time, map_words(&self.words))
+        };
+        line.background = map_words(&self.background);
+        if self.words.is_empty() {
+            line.text = f(&self.text).trim().to_string();
+        }
+        line.end = self.end;
+        line.agent = self.agent.clone();
+        line.translation = self
+            .translation
+            .as_deref()
+            .map(|text| f(text).trim().to_string())
+            .filter(|text| !text.is_empty());
+        line.romanization = self.romanization.as_deref().map(f);
+        line
+    }
+
+    /// How far the line has been sung at `position`, if it has word timings.
+    ///
+    /// Each word fills up until the next one starts. The last word fills up
+    /// until the end of the line, or at once if the end is not known.
+    pub fn progress(&self, position: Duration) -> Option<Progress> {
+        if self.words.is_empty() {
+            return None;
+        }
+        let mut sung = 0;
+        for (index, word) in self.words.iter().enumerate() {
+            if position < word.time {
+                break;
+            }
+            let singing = sung + word.text.len();
+            let fraction = match self.words.get(index + 1) {
+                Some(next) if position < next.time => {
+                    let elapsed = (position - word.time).as_secs_f64();
+                    let length = (next.time - word.time).as_secs_f64();
+                    elapsed / length
+                }
+                Some(_) => {
+                    sung = singing;
+                    continue;
+                }
+                None => match self.end {
+                    Some(end) if position < end => {
+                        let elapsed = (position - word.time).as_secs_f64();
+                        let length = (end - word.time).as_secs_f64();
+                        elapsed / length
+                    }
+                    _ => 1.,
+                },
+            };
+            return Some(Progress {
+                sung,
+                singing,
+                fraction,
+            });
+        }
+        Some(Progress {
+            sung,
+            singing: sung,
+            fraction: 0.,
+        })
+    }
+}
+
+/// What a lyrics document says about itself, i.e. the ID tags of LRC.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// The length of the recording the lyrics were made for.
+    pub length: Option<Duration>,
+    /// The author of the document.
+    pub by: Option<String>,
+    /// In milliseconds. Positive values make lines show up earlier.
+    pub offset: i64,
+    /// Whether the times are guessed, as for plain text, rather than synced.
+    pub estimated: bool,
+    /// Whether the lyrics were looked up and only the closest of poor
+    /// matches turned up, so they may well be those of another track.
+    pub poor_match: bool,
+}
+
+impl Metadata {
+    /// The position within the lyrics at `player_position`.
+    pub fn apply_offset(&self, player_position: Duration) -> Duration {
+        shift(player_position, self.offset)
+    }
+}
+
+/// Moves `position` by `millis`, stopping at zero.
+pub fn shift(position: Duration, millis: i64) -> Duration {
+    if millis >= 0 {
+        position + Duration::from_millis(millis as u64)
+    } else {
+        position
+            .checked_sub(Duration::from_millis(millis.unsigned_abs()))
+            .unwrap_or_default()
+    }
+}
+
+/// Timed lines of lyrics, sorted by time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Lyrics {
+    lines: Vec<Line>,
+    metadata: Metadata,
+}
+
+impl Lyrics {
+    pub fn new(mut lines: Vec<Line>) -> Self {
+        // Keep lines sharing the same time in document order.
+        lines.sort_by_key(|line| line.time);
+        Self {
+            lines,
+            metadata: Default::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Takes the lines of a parallel `translation` document as the
+    /// translations of the lines at the same times.
+    pub fn with_translation(mut self, translation: &Lyrics) -> Self {
+        for line in self.lines.iter_mut() {
+            if line.translation.is_some() {
+                continue;
+            }
+            let start = translation
+                .lines
+                .partition_point(|other| other.time < line.time);
+            line.translation = translation.lines[start..]
+                .iter()
+                .take_while(|other| other.time == line.time)
+                .find(|other| !other.text.is_empty())
+                .map(|other| other.text.clone());
+        }
+        self
+    }
+
+    /// Replaces each line with `f(line)`.
+    pub fn map_lines(self, f: impl FnMut(Line) -> Line) -> Self {
+        Self::new(self.lines.into_iter().map(f).collect()).with_metadata(self.metadata)
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The index of the line being sung at `position`, i.e. the last line
+    /// that starts no later than `position`. A line that has ended by then is
+    /// over only if the silence after it lasts at least `min_gap`, so that
+    /// it doesn't blink out between lines.
+    pub fn find_line_index(&self, position: Duration, min_gap: Duration) -> Option<usize> {
+        let index = match self.lines.partition_point(|line| line.time <= position) {
+            0 => return None,
+            index => index - 1,
+        };
+        let over = match self.lines[index].end {
+            Some(end) if end <= position => match self.lines.get(index + 1) {
+                Some(next) => next.time.saturating_sub(end) >= min_gap,
+                None => true,
+            },
+            _ => false,
+        };
+        if over {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    /// The start and the end of the silence at `position`, from the end of a
+    /// line, or the empty line that ends it, to the start of the next one, if
+    /// it lasts at least `min_length`. The intro before the first line counts
+    /// too.
+    pub fn find_gap(
+        &self,
+        position: Duration,
+        min_length: Duration,
+    ) -> Option<(Duration, Duration)> {
+        let started = self.lines.partition_point(|line| line.time <= position);
+        // Empty lines in a row make up one silence.
+        let mut first = started;
+        while first > 0 && self.lines[first - 1].text.is_empty() {
+            first -= 1;
+        }
+        let ended = first
+            .checked_sub(1)
+            .and_then(|index| self.lines[index].end)
+            .filter(|end| *end <= position);
+        let start = match ended {
+            Some(end) => end,
+            None if started == 0 => Duration::ZERO,
+            None if first < started => self.lines[first].time,
+            None => return None,
+        };
+        let end = self.lines[started..]
+            .iter()
+            .find(|line| !line.text.is_empty())?
+            .time;
+        Some((start, end)).filter(|(start, end)| *end - *start >= min_length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    /// Lines at 10s and 30s, the first ending at `end`.
+    fn lyrics(end: u64) -> Lyrics {
+        let mut first = Line::new(secs(10), "First");
+        first.end = Some(secs(end));
+        Lyrics::new(vec![first, Line::new(secs(30), "Second")])
+    }
+
+    #[test]
+    fn ends_lines_before_long_silences() {
+        let lyrics = lyrics(20);
+        assert_eq!(lyrics.find_line_index(secs(5), secs(3)), None);
+        assert_eq!(lyrics.find_line_index(secs(15), secs(3)), Some(0));
+        assert_eq!(lyrics.find_line_index(secs(25), secs(3)), None);
+        assert_eq!(lyrics.find_line_index(secs(30), secs(3)), Some(1));
+        assert_eq!(lyrics.find_gap(secs(15), secs(3)), None);
+        assert_eq!(
+            lyrics.find_gap(secs(25), secs(3)),
+            Some((secs(20), secs(30)))
+        );
+        assert_eq!(lyrics.find_gap(secs(25), secs(15)), None);
+    }
+
+    #[test]
+    fn keeps_lines_over_short_silences() {
+        let lyrics = lyrics(29);
+        assert_eq!(lyrics.find_line_index(secs(29), secs(3)), Some(0));
+        assert_eq!(lyrics.find_gap(secs(29), secs(3)), None);
+    }
+
+    #[test]
+    fn ends_the_last_line() {
+        let mut line = Line::new(secs(10), "Last");
+        line.end = Some(secs(20));
+        let lyrics = Lyrics::new(vec![line]);
+        assert_eq!(lyrics.find_line_index(secs(15), secs(3)), Some(0));
+        assert_eq!(lyrics.find_line_index(secs(25), secs(3)), None);
+    }
+
+    #[test]
+    fn joins_ends_and_empty_lines() {
+        let mut first = Line::new(secs(10), "First");
+        first.end = Some(secs(20));
+        let lyrics = Lyrics::new(vec![
+            first,
+            Line::new(secs(22), ""),
+            Line::new(secs(30), "Second"),
+        ]);
+        assert_eq!(
+            lyrics.find_gap(secs(21), secs(3)),
+            Some((secs(20), secs(30)))
+        );
+        assert_eq!(
+            lyrics.find_gap(secs(25), secs(3)),
+            Some((secs(20), secs(30)))
+        );
+    }
+}
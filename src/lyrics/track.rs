@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::QueryVariant;
+
+/// The metadata of a track that providers use to look up lyrics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Track {
+    pub name: String,
+    pub artist: String,
+    pub album: String,
+    /// Empty if the player doesn't know it.
+    pub album_artist: String,
+    pub duration: Option<Duration>,
+    /// The audio file, if the track is backed by one.
+    pub location: Option<PathBuf>,
+}
+
+impl Track {
+    /// A normalized identity of the track, insensitive to case and whitespace.
+    pub fn key(&self) -> String {
+        format!(
+            "{}\n{}",
+            normalize_key(&self.artist),
+            normalize_key(&self.name)
+        )
+    }
+
+    /// The track as it is to be queried with `variant`.
+    pub fn with_variant(&self, variant: &QueryVariant) -> Self {
+        Self {
+            name: variant.name.clone(),
+            artist: variant.artist.clone(),
+            ..self.clone()
+        }
+    }
+}
+
+fn normalize_key(s: &str) -> String {
+    s.split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
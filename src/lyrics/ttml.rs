@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use roxmltree::Document;
+use roxmltree::Node;
+
+use super::format::out_of_order;
+use super::format::Diagnostic;
+use super::format::Parsed;
+use super::Line;
+use super::Lyrics;
+use super::Metadata;
+use super::Word;
+
+/// Parses a TTML document, such as the syllable-synced lyrics of Apple Music.
+///
+/// Each `<p>` becomes a line that ends at its `end`, and its timed `<span>`s
+/// become the words of the line. Spans with `ttm:role="x-bg"` are background
+/// vocals, and those with `x-translation` and `x-roman` the translation and
+/// romanization of the line. The singer comes from `ttm:agent`, by name if
+/// the head defines one. Paragraphs without a usable `begin` are skipped.
+pub fn parse(content: &str) -> Parsed {
+    let document = match Document::parse(content.trim_start_matches('\u{feff}')) {
+        Ok(document) => document,
+        Err(e) => {
+            return Parsed {
+                lyrics: Lyrics::default(),
+                diagnostics: vec![Diagnostic {
+                    line: e.pos().row as usize,
+                    message: e.to_string(),
+                }],
+                out_of_order: vec![],
+            }
+        }
+    };
+    let root = document.root_element();
+    let agents = agent_names(root);
+    let translations = translations(root);
+    let metadata = Metadata {
+        title: root
+            .descendants()
+            .find(|node| node.tag_name().name() == "title")
+            .map(text_content)
+            .filter(|title| !title.is_empty()),
+        length: root
+            .descendants()
+            .find(|node| node.tag_name().name() == "body")
+            .and_then(|body| attribute(body, "dur"))
+            .and_then(parse_time),
+        ..Default::default()
+    };
+    let mut lines = vec![];
+    let mut diagnostics = vec![];
+    for p in root
+        .descendants()
+        .filter(|node| node.tag_name().name() == "p")
+    {
+        let mut diagnose = |message: String| {
+            diagnostics.push(Diagnostic {
+                line: document.text_pos_at(p.range().start).row as usize,
+                message,
+            })
+        };
+        let time = match attribute(p, "begin").map(|begin| (begin, parse_time(begin))) {
+            Some((_, Some(time))) => time,
+            Some((begin, None)) => {
+                diagnose(format!("invalid begin {}", begin));
+                continue;
+            }
+            None => {
+                diagnose("no begin".to_string());
+                continue;
+            }
+        };
+        let mut line = Line::new(time, "");
+        line.end = attribute(p, "end").and_then(parse_time);
+        // The agent may be set on the enclosing <div> as well.
+        line.agent = p
+            .ancestors()
+            .find_map(|node| attribute(node, "agent"))
+            .map(|agent| {
+                agents
+                    .get(agent)
+                    .cloned()
+                    .unwrap_or_else(|| agent.to_string())
+            });
+        let mut words = WordsBuilder::new(time);
+        let mut background = WordsBuilder::new(time);
+        collect(p, &mut line, &mut words, &mut background);
+        if words.timed {
+            line.set_words(words.finish());
+        } else {
+            let text = words
+                .finish()
+                .iter()
+                .map(|word| word.text.as_str())
+                .collect::<String>();
+            line.text = text.trim().to_string();
+        }
+        line.background = background.finish();
+        if line.translation.is_none() {
+            line.translation = attribute(p, "key").and_then(|key| translations.get(key).cloned());
+        }
+        lines.push(line);
+    }
+    Parsed {
+        out_of_order: out_of_order(lines.iter().map(|line| line.time)),
+        lyrics: Lyrics::new(lines).with_metadata(metadata),
+        diagnostics,
+    }
+}
+
+/// Collects the words of `node` into `words`, those of background vocals into
+/// `background` and the other texts into `line`.
+fn collect(node: Node, line: &mut Line, words: &mut WordsBuilder, background: &mut WordsBuilder) {
+    for child in node.children() {
+        if let Some(text) = child.text().filter(|_| child.is_text()) {
+            words.push_text(text);
+            continue;
+        }
+        if !child.is_element() {
+            continue;
+        }
+        match child.tag_name().name() {
+            "br" => {
+                words.push_text(" ");
+                continue;
+            }
+            "span" => {}
+            _ => continue,
+        }
+        match attribute(child, "role") {
+            Some("x-bg") => {
+                let mut nested = WordsBuilder::new(background.line_time);
+                collect(child, line, background, &mut nested);
+            }
+            Some("x-translation") => line.translation = Some(text_content(child)),
+            Some("x-roman") => line.romanization = Some(text_content(child)),
+            _ => match attribute(child, "begin").and_then(parse_time) {
+                Some(begin) => {
+                    let end = attribute(child, "end").and_then(parse_time);
+                    words.push_syllable(begin, end, &text_content(child));
+                }
+                // An untimed span only groups other spans or texts.
+                None => collect(child, line, words, background),
+            },
+        }
+    }
+}
+
+/// Builds the words of a line from a run of syllables and texts.
+struct WordsBuilder {
+    line_time: Duration,
+    words: Vec<Word>,
+    /// The end of the last syllable, marked once the next one starts later.
+    pending_end: Option<Duration>,
+    /// Whether any syllable has its own timing.
+    timed: bool,
+}
+
+impl WordsBuilder {
+    fn new(line_time: Duration) -> Self {
+        Self {
+            line_time,
+            words: vec![],
+            pending_end: None,
+            timed: false,
+        }
+    }
+
+    /// Adds text that goes with the last syllable, e.g. the space after it.
+    fn push_text(&mut self, text: &str) {
+        match self.words.last_mut() {
+            Some(word) => word.text.push_str(text),
+            None if !text.trim().is_empty() => self.words.push(Word::new(self.line_time, text)),
+            None => {}
+        }
+    }
+
+    fn push_syllable(&mut self, begin: Duration, end: Option<Duration>, text: &str) {
+        if let Some(pending_end) = self.pending_end.take() {
+            if pending_end < begin {
+                self.words.push(Word::new(pending_end, ""));
+            }
+        }
+        self.words.push(Word::new(begin, text));
+        self.pending_end = end;
+        self.timed = true;
+    }
+
+    fn finish(mut self) -> Vec<Word> {
+        if let Some(pending_end) = self.pending_end.take() {
+            self.words.push(Word::new(pending_end, ""));
+        }
+        self.words
+    }
+}
+
+/// The names of the singers declared in the head, by ID.
+fn agent_names(root: Node) -> HashMap<String, String> {
+    root.descendants()
+        .filter(|node| node.tag_name().name() == "agent")
+        .filter_map(|agent| {
+            let id = attribute(agent, "id")?;
+            let name = agent
+                .children()
+                .find(|node| node.tag_name().name() == "name")
+                .map(text_content)
+                .filter(|name| !name.is_empty())?;
+            Some((id.to_string(), name))
+        })
+        .collect()
+}
+
+/// The translations in the iTunes metadata of the head, by line key.
+fn translations(root: Node) -> HashMap<String, String> {
+    root.descendants()
+        .filter(|node| node.tag_name().name() == "translation")
+        .flat_map(|translation| translation.children())
+        .filter(|node| node.tag_name().name() == "text")
+        .filter_map(|text| Some((attribute(text, "for")?.to_string(), text_content(text))))
+        .collect()
+}
+
+/// The value of the attribute `name` in any namespace, since documents in the
+/// wild are not consistent about them.
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .iter()
+        .find(|attribute| attribute.name() == name)
+        .map(|attribute| attribute.value())
+}
+
+fn text_content(node: Node) -> String {
+    node.descendants()
+        .filter(|node| node.is_text())
+        .filter_map(|node| node.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Parses a time expression: a clock time like `1:02.345` or `00:01:02.345`,
+/// or an offset time like `62.345s`, `62345ms` or a bare number of seconds.
+fn parse_time(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let seconds = if value.contains(':') {
+        let parts = value.split(':').collect::<Vec<_>>();
+        if parts.len() > 3 {
+            return None;
+        }
+        let (last, rest) = parts.split_last()?;
+        let mut seconds = 0.;
+        for part in rest {
+            seconds = seconds * 60. + part.parse::<u64>().ok()? as f64;
+        }
+        seconds * 60. + last.parse::<f64>().ok()?
+    } else {
+        let (number, scale) = if let Some(number) = value.strip_suffix("ms") {
+            (number, 0.001)
+        } else if let Some(number) = value.strip_suffix('h') {
+            (number, 3600.)
+        } else if let Some(number) = value.strip_suffix('m') {
+            (number, 60.)
+        } else if let Some(number) = value.strip_suffix('s') {
+            (number, 1.)
+        } else {
+            (value, 1.)
+        };
+        number.parse::<f64>().ok()? * scale
+    };
+    if !seconds.is_finite() || seconds < 0. {
+        return None;
+    }
+    Some(Duration::from_millis((seconds * 1000.).round() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTML: &str = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:itunes="http://music.apple.com/lyric-ttml-internal">
+  <head>
+    <metadata>
+      <ttm:agent type="person" xml:id="v1"><ttm:name type="full">Alice</ttm:name></ttm:agent>
+      <iTunesMetadata>
+        <translations><translation lang="zh"><text for="L2">第二行</text></translation></translations>
+      </iTunesMetadata>
+    </metadata>
+  </head>
+  <body dur="00:10.000">
+    <div>
+      <p begin="00:01.000" end="00:03.000" ttm:agent="v1"><span begin="00:01.000" end="00:01.500">Hel</span><span begin="00:01.500" end="00:02.000">lo</span> <span begin="00:02.200" end="00:02.800">world</span><span ttm:role="x-bg"><span begin="00:02.500" end="00:03.000">(yeah)</span></span><span ttm:role="x-translation">你好世界</span></p>
+      <p begin="3.5s" end="5s" itunes:key="L2">Second line</p>
+      <p>No begin</p>
+      <p begin="soon">Invalid begin</p>
+    </div>
+  </body>
+</tt>"#;
+
+    #[test]
+    fn reads_lines_and_their_syllables() {
+        let parsed = parse(TTML);
+        let lines = parsed.lyrics.lines();
+        assert_eq!(lines.len(), 2);
+        let line = &lines[0];
+        assert_eq!(line.time, Duration::from_secs(1));
+        assert_eq!(line.end, Some(Duration::from_secs(3)));
+        assert_eq!(line.text, "Hello world");
+        assert_eq!(line.agent.as_deref(), Some("Alice"));
+        assert_eq!(line.translation.as_deref(), Some("你好世界"));
+        let words = line
+            .words
+            .iter()
+            .filter(|word| !word.text.is_empty())
+            .map(|word| (word.time, word.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            words,
+            [
+                (Duration::from_secs(1), "Hel"),
+                (Duration::from_millis(1500), "lo "),
+                (Duration::from_millis(2200), "world"),
+            ]
+        );
+        assert_eq!(line.background[0].text, "(yeah)");
+        assert_eq!(line.background[0].time, Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn reads_translations_and_length_from_the_head() {
+        let parsed = parse(TTML);
+        let line = &parsed.lyrics.lines()[1];
+        assert_eq!(line.time, Duration::from_millis(3500));
+        assert_eq!(line.text, "Second line");
+        assert_eq!(line.translation.as_deref(), Some("第二行"));
+        assert_eq!(
+            parsed.lyrics.metadata().length,
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn reports_paragraphs_it_skips() {
+        let parsed = parse(TTML);
+        let diagnostics = parsed
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            ["line 14: no begin", "line 15: invalid begin soon"]
+        );
+        let parsed = parse("<tt><p begin=1s>a</p></tt>");
+        assert!(parsed.lyrics.is_empty());
+        assert_eq!(parsed.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn reads_clock_and_offset_times() {
+        let ms = Duration::from_millis;
+        assert_eq!(parse_time("1:02.345"), Some(ms(62_345)));
+        assert_eq!(parse_time("01:01:02.345"), Some(ms(3_662_345)));
+        assert_eq!(parse_time("62.345s"), Some(ms(62_345)));
+        assert_eq!(parse_time("62345ms"), Some(ms(62_345)));
+        assert_eq!(parse_time("1.5m"), Some(ms(90_000)));
+        assert_eq!(parse_time("62.345"), Some(ms(62_345)));
+        assert_eq!(parse_time("-1s"), None);
+        assert_eq!(parse_time("1:2:3:4"), None);
+    }
+}
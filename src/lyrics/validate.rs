@@ -0,0 +1,434 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::export::format_lrc_timestamp;
+use super::format;
+use super::Line;
+use super::Lyrics;
+
+/// Lines with the same text starting this close are the same line written
+/// twice; nobody sings a line again that fast.
+const DUPLICATE_WINDOW: Duration = Duration::from_millis(200);
+
+/// Lines running past the end of the track by less than this are not taken
+/// as shifted.
+const MIN_SHIFT: Duration = Duration::from_secs(1);
+
+/// How many times a finding lists before summing up the rest.
+const MAX_LISTED_TIMES: usize = 3;
+
+/// How bad a problem with the times of lyrics is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Nothing shows up differently, e.g. an empty line that is never shown.
+    Info,
+    /// Lines show up at the wrong time, twice, or not at all.
+    Warning,
+    /// The lyrics are probably not for this track.
+    Error,
+}
+
+/// A problem found in the times of lyrics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    /// Whether [`validate`] repaired the problem, rather than only reporting
+    /// it.
+    pub repaired: bool,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if self.repaired {
+            write!(f, " (repaired)")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Validated {
+    pub lyrics: Lyrics,
+    pub findings: Vec<Finding>,
+}
+
+/// Checks the times of `lyrics` for the problems common in documents found
+/// in the wild, and repairs those that can be repaired without guessing.
+///
+/// Lines are sorted by [`Lyrics`] itself, so those that were out of order in
+/// the document are only reported, from the `out_of_order` times of
+/// [`Parsed`](super::format::Parsed). Otherwise, what can be out of order are
+/// the words of a line and the ends of lines; those are sorted by moving the
+/// times that go backwards forward. Duplicated lines are dropped, and lines
+/// that are never shown because another starts at the same time are
+/// collapsed. Lines that an `[offset:]` moves before the start of the track
+/// are reported. Given the `duration` of the track, lines starting past its
+/// end are dropped and the rest end by then, unless the lyrics seem to be
+/// shifted as a whole, which is only reported: the offset hotkeys are there
+/// for that.
+pub fn validate(
+    lyrics: Lyrics,
+    out_of_order: &[Duration],
+    duration: Option<Duration>,
+) -> Validated {
+    let metadata = lyrics.metadata().clone();
+    let mut lines = lyrics.lines().to_vec();
+    let mut findings = vec![];
+    report(
+        &mut findings,
+        Severity::Warning,
+        "Lines out of order",
+        out_of_order,
+        true,
+    );
+    sort_times(&mut lines, &mut findings);
+    remove_duplicates(&mut lines, &mut findings);
+    collapse_zero_length(&mut lines, &mut findings);
+    check_start(&lines, metadata.apply_offset(Duration::ZERO), &mut findings);
+    if let Some(duration) = duration.filter(|duration| !duration.is_zero()) {
+        clamp(&mut lines, metadata.apply_offset(duration), &mut findings);
+    }
+    Validated {
+        lyrics: Lyrics::new(lines).with_metadata(metadata),
+        findings,
+    }
+}
+
+/// Whether the lyrics document `content` is probably for another track than
+/// one lasting `duration`, i.e. [`validate`] finds an [`Severity::Error`].
+pub fn is_for_another_track(content: &str, duration: Option<Duration>) -> bool {
+    let parsed = format::parse(content);
+    validate(parsed.lyrics, &parsed.out_of_order, duration)
+        .findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error)
+}
+
+/// Moves word times that go backwards to the time before them, and drops
+/// ends that come before the start of their line or its last word.
+fn sort_times(lines: &mut [Line], findings: &mut Vec<Finding>) {
+    let mut times = vec![];
+    for line in lines.iter_mut() {
+        let mut sorted = true;
+        let mut last = line.time;
+        for words in [&mut line.words, &mut line.background] {
+            let mut previous = line.time;
+            for word in words.iter_mut() {
+                if word.time < previous {
+                    word.time = previous;
+                    sorted = false;
+                }
+                previous = word.time;
+            }
+            last = last.max(previous);
+        }
+        if line.end.map(|end| end < last).unwrap_or(false) {
+            line.end = None;
+            sorted = false;
+        }
+        if !sorted {
+            times.push(line.time);
+        }
+    }
+    report(
+        findings,
+        Severity::Warning,
+        "Times out of order in the lines",
+        &times,
+        true,
+    );
+}
+
+/// Drops lines repeating the line just before them, keeping its translation
+/// if the first has none.
+fn remove_duplicates(lines: &mut Vec<Line>, findings: &mut Vec<Finding>) {
+    let mut kept: Vec<Line> = vec![];
+    let mut times = vec![];
+    for line in lines.drain(..) {
+        let original = kept
+            .iter_mut()
+            .rev()
+            .take_while(|other| line.time - other.time <= DUPLICATE_WINDOW)
+            .find(|other| !other.text.is_empty() && other.text == line.text);
+        match original {
+            Some(original) => {
+                if original.translation.is_none() {
+                    original.translation = line.translation;
+                }
+                times.push(line.time);
+            }
+            None => kept.push(line),
+        }
+    }
+    *lines = kept;
+    report(
+        findings,
+        Severity::Warning,
+        "Duplicated lines",
+        &times,
+        true,
+    );
+}
+
+/// Deals with lines that are never shown: their ends are dropped if they end
+/// as they start, and empty lines starting along with others are dropped.
+/// Two lines with text at the same time are reported, since either could be
+/// the one meant.
+fn collapse_zero_length(lines: &mut Vec<Line>, findings: &mut Vec<Finding>) {
+    let mut collapsed: Vec<Line> = vec![];
+    let mut collapsed_times = vec![];
+    let mut hidden_times = vec![];
+    for mut line in lines.drain(..) {
+        if line.end == Some(line.time) {
+            line.end = None;
+            collapsed_times.push(line.time);
+        }
+        match collapsed.last_mut() {
+            Some(last) if last.time == line.time => {
+                if last.text.is_empty() {
+                    *last = line;
+                    collapsed_times.push(last.time);
+                } else if line.text.is_empty() {
+                    collapsed_times.push(line.time);
+                } else {
+                    hidden_times.push(line.time);
+                    collapsed.push(line);
+                }
+            }
+            _ => collapsed.push(line),
+        }
+    }
+    *lines = collapsed;
+    collapsed_times.dedup();
+    report(
+        findings,
+        Severity::Info,
+        "Lines without length",
+        &collapsed_times,
+        true,
+    );
+    report(
+        findings,
+        Severity::Warning,
+        "Lines hidden by others at the same time",
+        &hidden_times,
+        false,
+    );
+}
+
+/// Reports lines that start before the track does at `start`, in the times of
+/// the lyrics, which is later than zero for a positive `[offset:]`. They all
+/// show up at once as the track starts.
+fn check_start(lines: &[Line], start: Duration, findings: &mut Vec<Finding>) {
+    let first = lines
+        .iter()
+        .find(|line| !line.text.is_empty())
+        .map(|line| line.time);
+    if let Some(first) = first.filter(|&first| first < start) {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            message: format!(
+                "Lines seem to be early by at least {:.1}s",
+                (start - first).as_secs_f64()
+            ),
+            repaired: false,
+        });
+    }
+}
+
+/// Fits the lines into a track ending at `end`, in the times of the lyrics.
+fn clamp(lines: &mut Vec<Line>, end: Duration, findings: &mut Vec<Finding>) {
+    let sung = lines
+        .iter()
+        .filter(|line| !line.text.is_empty())
+        .map(|line| line.time)
+        .collect::<Vec<_>>();
+    let past = sung
+        .iter()
+        .copied()
+        .filter(|&time| time >= end)
+        .collect::<Vec<_>>();
+    if !past.is_empty() {
+        if past.len() == sung.len() {
+            report(
+                findings,
+                Severity::Error,
+                "All lines start after the end of the track",
+                &past,
+                false,
+            );
+            return;
+        }
+        // Room to move every line back into the track hints at a constant
+        // shift, e.g. lyrics timed for a version with a longer intro.
+        let overflow = sung[sung.len() - 1] - end;
+        if overflow >= MIN_SHIFT && sung[0] >= overflow {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!(
+                    "Lines seem to be late by at least {:.1}s",
+                    overflow.as_secs_f64()
+                ),
+                repaired: false,
+            });
+            return;
+        }
+        report(
+            findings,
+            Severity::Warning,
+            "Lines after the end of the track",
+            &past,
+            true,
+        );
+    }
+    let mut times = vec![];
+    lines.retain(|line| line.time < end);
+    for line in lines.iter_mut() {
+        let mut clamped = false;
+        if line.end.map(|time| time > end).unwrap_or(false) {
+            line.end = Some(end);
+            clamped = true;
+        }
+        for word in line.words.iter_mut().chain(line.background.iter_mut()) {
+            if word.time > end {
+                word.time = end;
+                clamped = true;
+            }
+        }
+        if clamped {
+            times.push(line.time);
+        }
+    }
+    report(
+        findings,
+        Severity::Info,
+        "Lines ending after the end of the track",
+        &times,
+        true,
+    );
+}
+
+/// Adds a finding about the lines at `times`, if any.
+fn report(
+    findings: &mut Vec<Finding>,
+    severity: Severity,
+    what: &str,
+    times: &[Duration],
+    repaired: bool,
+) {
+    if times.is_empty() {
+        return;
+    }
+    let mut listed = times
+        .iter()
+        .take(MAX_LISTED_TIMES)
+        .map(|&time| format_lrc_timestamp(time))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if times.len() > MAX_LISTED_TIMES {
+        listed += &format!(" and {} more", times.len() - MAX_LISTED_TIMES);
+    }
+    findings.push(Finding {
+        severity,
+        message: format!("{} at {}", what, listed),
+        repaired,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyrics::format;
+
+    fn validate_lrc(lrc: &str, duration: Option<Duration>) -> Validated {
+        let parsed = format::parse(lrc);
+        validate(parsed.lyrics, &parsed.out_of_order, duration)
+    }
+
+    fn messages(validated: &Validated) -> Vec<String> {
+        validated
+            .findings
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect()
+    }
+
+    fn texts(validated: &Validated) -> Vec<&str> {
+        validated
+            .lyrics
+            .lines()
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn reports_lines_out_of_order() {
+        let validated = validate_lrc(
+            "[00:05.00]b\n[00:01.00]a\n[00:10.00][00:30.00]chorus\n[00:12.00]c\n",
+            None,
+        );
+        assert_eq!(
+            messages(&validated),
+            ["Lines out of order at 00:01.00 (repaired)"]
+        );
+        assert_eq!(texts(&validated), ["a", "b", "chorus", "c", "chorus"]);
+    }
+
+    #[test]
+    fn sorts_word_times() {
+        let validated = validate_lrc("[00:01.00]<00:01.00>a <00:03.00>b <00:02.00>c\n", None);
+        assert_eq!(
+            messages(&validated),
+            ["Times out of order in the lines at 00:01.00 (repaired)"]
+        );
+        let words = &validated.lyrics.lines()[0].words;
+        assert_eq!(words[2].time, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn removes_duplicates() {
+        let validated = validate_lrc("[00:01.00]a\n[00:01.10]a\n[00:03.00]b\n", None);
+        assert_eq!(
+            messages(&validated),
+            ["Duplicated lines at 00:01.10 (repaired)"]
+        );
+        assert_eq!(texts(&validated), ["a", "b"]);
+    }
+
+    #[test]
+    fn reports_early_lyrics() {
+        let validated = validate_lrc("[offset:2000]\n[00:01.00]a\n[00:05.00]b\n", None);
+        assert_eq!(
+            messages(&validated),
+            ["Lines seem to be early by at least 1.0s"]
+        );
+    }
+
+    #[test]
+    fn reports_late_lyrics() {
+        let validated = validate_lrc("[00:05.00]a\n[00:12.00]b\n", Some(Duration::from_secs(10)));
+        assert_eq!(
+            messages(&validated),
+            ["Lines seem to be late by at least 2.0s"]
+        );
+        assert_eq!(texts(&validated), ["a", "b"]);
+    }
+
+    #[test]
+    fn drops_lines_past_the_end() {
+        let validated = validate_lrc("[00:01.00]a\n[00:10.50]b\n", Some(Duration::from_secs(10)));
+        assert_eq!(
+            messages(&validated),
+            ["Lines after the end of the track at 00:10.50 (repaired)"]
+        );
+        assert_eq!(texts(&validated), ["a"]);
+    }
+
+    #[test]
+    fn rejects_lyrics_past_the_end() {
+        let validated = validate_lrc("[00:20.00]a\n", Some(Duration::from_secs(10)));
+        assert_eq!(validated.findings[0].severity, Severity::Error);
+    }
+}
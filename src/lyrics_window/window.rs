@@ -1,5 +1,7 @@
+use std::path::PathBuf;
 use std::ptr::null;
 use std::ptr::null_mut;
+use std::thread;
 use std::time::Duration;
 
 use anyhow::Result;
@@ -12,19 +14,43 @@ use bindings::Windows::Win32::Graphics::DirectWrite::*;
 use bindings::Windows::Win32::Graphics::Dxgi::*;
 use bindings::Windows::Win32::UI::Animation::*;
 use bindings::Windows::Win32::UI::WindowsAndMessaging::*;
-use lrc::Lyrics;
+use log::info;
+use log::warn;
 use once_cell::sync::OnceCell;
 use windows::*;
 
+use crate::config::Config;
+use crate::config::DisplayConfig;
+use crate::config::DisplayLines;
+use crate::config::GapIndicator;
+use crate::config::HotkeysConfig;
+use crate::config::RomanizationDisplay;
+use crate::lyrics::retime;
+use crate::lyrics::save_to_folder;
+use crate::lyrics::shift;
+use crate::lyrics::Anchor;
+use crate::lyrics::CorrectionStore;
+use crate::lyrics::Line;
+use crate::lyrics::Lyrics;
+use crate::lyrics::OffsetStore;
+use crate::lyrics::Progress;
 use crate::lyrics::Query;
+use crate::lyrics::TapSync;
+use crate::lyrics::Track;
+use crate::paths::corrections_directory;
+use crate::paths::offsets_file;
 use crate::player::itunes::ITunes;
 use crate::player::Player;
 use crate::player::PlayerState;
 use crate::ui::get_window_instance;
+use crate::ui::hotkey::*;
 use crate::ui::utils::*;
 use crate::ui::Window;
 
+/// Tall enough for a line with one secondary text.
 const WINDOW_HEIGHT: i32 = 80;
+/// The room taken by each further secondary text, in the 16pt font.
+const SECONDARY_TEXT_HEIGHT: i32 = 24;
 const PADDING_HORIZONTAL: f64 = 10.;
 const PADDING_VERTICAL: f64 = 5.;
 
@@ -33,6 +59,117 @@ const DURATION_FADE_OUT: Duration = Duration::from_millis(800);
 const DURATION_SIZING: Duration = Duration::from_millis(200);
 const DURATION_SCROLLING: Duration = Duration::from_millis(350);
 
+/// Posted by the lookup tasks of [`Query`] once lyrics are ready.
+const WM_LYRICS_READY: u32 = WM_APP + 1;
+
+const HOTKEY_OFFSET_EARLIER: i32 = 1;
+const HOTKEY_OFFSET_LATER: i32 = 2;
+const HOTKEY_OFFSET_RESET: i32 = 3;
+const HOTKEY_ANCHOR: i32 = 4;
+const HOTKEY_CORRECTION_DISCARD: i32 = 5;
+const HOTKEY_AUTHORING: i32 = 6;
+/// Registered only while authoring, like [`HOTKEY_UNDO`].
+const HOTKEY_TAP: i32 = 7;
+const HOTKEY_UNDO: i32 = 8;
+
+/// How far each press of the offset hotkeys moves the lyrics, in milliseconds.
+const OFFSET_STEP: i64 = 100;
+
+/// How far each press of the offset hotkeys moves a line while authoring, in
+/// milliseconds.
+const FINE_ADJUST_STEP: i64 = 50;
+
+/// The opacity of lyrics with estimated times, which tells them apart from
+/// synced ones.
+const ESTIMATED_TEXT_OPACITY: f32 = 0.6;
+
+/// A line of lyrics as it is shown, according to the display settings.
+#[derive(Debug, Clone, PartialEq)]
+struct DisplayLine {
+    text: String,
+    /// Shown above the text in a smaller font, like ruby.
+    annotation: Option<String>,
+    /// Shown below the text in a smaller font.
+    secondary: Vec<String>,
+}
+
+impl DisplayLine {
+    fn new(line: &Line, display: &DisplayConfig) -> Self {
+        let (text, translation) = match (display.lines, line.translation.as_ref()) {
+            (DisplayLines::Translation, Some(translation)) => (translation.clone(), None),
+            (DisplayLines::Both, translation) => (line.text.clone(), translation.cloned()),
+            _ => (line.text.clone(), None),
+        };
+        let mut annotation = None;
+        let mut secondary = vec![];
+        // The romanization is of the original text.
+        if text == line.text {
+            match display.romanization {
+                RomanizationDisplay::Off => {}
+                RomanizationDisplay::Ruby => annotation = line.romanization.clone(),
+                RomanizationDisplay::Line => secondary.extend(line.romanization.clone()),
+            }
+        }
+        secondary.extend(translation);
+        Self {
+            text,
+            annotation,
+            secondary,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// The texts from top to bottom, each with whether it is the text itself
+    /// rather than an annotation or a secondary text.
+    fn parts(&self) -> Vec<(&str, bool)> {
+        let mut parts = vec![];
+        parts.extend(self.annotation.as_deref().map(|text| (text, false)));
+        parts.push((self.text.as_str(), true));
+        parts.extend(self.secondary.iter().map(|text| (text.as_str(), false)));
+        parts
+    }
+}
+
+/// The height of the window, so that the texts shown with each line as set in
+/// `display` are not clipped: the romanization above or below it, and the
+/// translation below it.
+fn window_height(display: &DisplayConfig) -> i32 {
+    let secondary = (display.romanization != RomanizationDisplay::Off) as i32
+        + (display.lines == DisplayLines::Both) as i32;
+    WINDOW_HEIGHT + SECONDARY_TEXT_HEIGHT * (secondary - 1).max(0)
+}
+
+/// Tells the user which hotkeys could not be registered, if any, e.g. since
+/// another program has taken them. The message box has a thread of its own,
+/// so as not to pump the messages of the lyrics window meanwhile.
+fn report_unregistered(hotkeys: &[&str]) {
+    if hotkeys.is_empty() {
+        return;
+    }
+    let message = format!(
+        "Failed to register {}. Another program may be using them; other hotkeys can be set in config.toml.",
+        hotkeys.join(", ")
+    );
+    thread::spawn(move || unsafe {
+        MessageBoxW(None, message.as_str(), "iLyrics", MB_ICONWARNING | MB_OK);
+    });
+}
+
+struct LineMetrics {
+    width: f32,
+    /// The heights of [`DisplayLine::parts`].
+    heights: Vec<f32>,
+}
+
+impl LineMetrics {
+    fn height(&self) -> f32 {
+        self.heights.iter().sum()
+    }
+}
+
 struct Resources {
     d2d_factory: ID2D1Factory2,
     dc: ID2D1DeviceContext,
@@ -42,6 +179,8 @@ struct Resources {
     dwrite_factory: IDWriteFactory2,
     brush: ID2D1SolidColorBrush,
     text_format: IDWriteTextFormat1,
+    /// For translations and other texts below a line.
+    secondary_text_format: IDWriteTextFormat1,
     animation_manager: IUIAnimationManager,
     animation_timer: IUIAnimationTimer,
     animation_transition_library: IUIAnimationTransitionLibrary,
@@ -58,11 +197,34 @@ pub struct LyricsWindow {
     resources: OnceCell<Resources>,
     player: ITunes,
     query: Query,
+    offsets: OffsetStore,
+    corrections: CorrectionStore,
+    /// Where lyrics synced by the user are saved, if set.
+    lyrics_directory: Option<PathBuf>,
+    track: Option<Track>,
+    /// The offset of the current track set by the user, in milliseconds.
+    user_offset: i64,
     lyrics: Option<Lyrics>,
+    /// The lyrics as looked up, which [`Self::lyrics`] shows converted and
+    /// romanized as set. Corrections are made to these.
+    source: Option<Lyrics>,
+    /// The lyrics as they were before the user started to anchor lines, which
+    /// [`Self::anchors`] refer to.
+    uncorrected: Option<Lyrics>,
+    anchors: Vec<Anchor>,
+    /// The lyrics being synced by the user, which take over the hotkeys.
+    authoring: Option<TapSync>,
     player_position: Option<Duration>,
-    line_current: Option<String>,
-    line_next: Option<String>,
-    line_next_non_empty: Option<String>,
+    display: DisplayConfig,
+    hotkeys: HotkeysConfig,
+    line_current: Option<DisplayLine>,
+    line_next: Option<DisplayLine>,
+    line_next_non_empty: Option<DisplayLine>,
+    /// The index of the line of [`Self::line_next_non_empty`].
+    karaoke_line: Option<usize>,
+    karaoke: Option<Progress>,
+    /// Whether the gap indicator is being shown instead of a line.
+    in_gap: bool,
 }
 
 impl Window for LyricsWindow {
@@ -83,6 +245,8 @@ impl Window for LyricsWindow {
     fn window_proc(&mut self, hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
         match msg {
             WM_TIMER => self.on_timer(hwnd, msg, wparam, lparam),
+            WM_LYRICS_READY => self.on_lyrics_ready(hwnd, msg, wparam, lparam),
+            WM_HOTKEY => self.on_hotkey(hwnd, msg, wparam, lparam),
             WM_DESTROY => self.on_destroy(hwnd, msg, wparam, lparam),
             _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
         }
@@ -90,23 +254,39 @@ impl Window for LyricsWindow {
 }
 
 impl LyricsWindow {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: &Config) -> Result<Self> {
         let (_scale_x, scale_y) = get_scale_factor()?;
         let mut rect = get_workarea_rect()?;
-        rect.top = rect.bottom - (WINDOW_HEIGHT as f32 * scale_y).round() as i32;
+        rect.top = rect.bottom - (window_height(&config.display) as f32 * scale_y).round() as i32;
         let hwnd = Self::create_window("iLyrics", &rect, None)?;
         let player = ITunes::new()?;
-        let query = Query::new();
+        let query = Query::new(config, move || unsafe {
+            PostMessageW(hwnd, WM_LYRICS_READY, WPARAM(0), LPARAM(0));
+        })?;
         Ok(Self {
             hwnd,
             resources: OnceCell::new(),
             player,
             query,
+            offsets: OffsetStore::load(offsets_file()),
+            corrections: CorrectionStore::new(corrections_directory()),
+            lyrics_directory: config.lyrics.directory.clone(),
+            track: None,
+            user_offset: 0,
             lyrics: None,
+            source: None,
+            uncorrected: None,
+            anchors: vec![],
+            authoring: None,
             player_position: None,
+            display: config.display.clone(),
+            hotkeys: config.hotkeys.clone(),
             line_current: None,
             line_next: None,
             line_next_non_empty: None,
+            karaoke_line: None,
+            karaoke: None,
+            in_gap: false,
         })
     }
 
@@ -115,9 +295,70 @@ impl LyricsWindow {
         Window::show(self, SW_SHOWNOACTIVATE)?;
         self.draw()?;
         self.set_lyrics_timer()?;
+        self.register_hotkeys();
         Ok(())
     }
 
+    fn register_hotkeys(&self) {
+        let hotkeys = &self.hotkeys;
+        let failed = [
+            (HOTKEY_OFFSET_EARLIER, &hotkeys.offset_earlier),
+            (HOTKEY_OFFSET_LATER, &hotkeys.offset_later),
+            (HOTKEY_OFFSET_RESET, &hotkeys.offset_reset),
+            (HOTKEY_ANCHOR, &hotkeys.anchor),
+            (HOTKEY_CORRECTION_DISCARD, &hotkeys.discard),
+            (HOTKEY_AUTHORING, &hotkeys.sync),
+        ]
+        .iter()
+        .filter(|(id, hotkey)| !self.register_hotkey(*id, hotkey))
+        .map(|(_, hotkey)| hotkey.as_str())
+        .collect::<Vec<_>>();
+        report_unregistered(&failed);
+    }
+
+    /// Takes the hotkeys for tapping lines while authoring, and gives them
+    /// back to other programs afterwards.
+    fn register_authoring_hotkeys(&self, register: bool) {
+        let hotkeys = [
+            (HOTKEY_TAP, &self.hotkeys.tap),
+            (HOTKEY_UNDO, &self.hotkeys.undo),
+        ];
+        if !register {
+            for (id, hotkey) in hotkeys.iter() {
+                if let Err(e) = unregister_hotkey(self.hwnd, *id) {
+                    warn!("Failed to unregister {}: {:?}", hotkey, e);
+                }
+            }
+            return;
+        }
+        let failed = hotkeys
+            .iter()
+            .filter(|(id, hotkey)| !self.register_hotkey(*id, hotkey))
+            .map(|(_, hotkey)| hotkey.as_str())
+            .collect::<Vec<_>>();
+        report_unregistered(&failed);
+    }
+
+    /// Registers `hotkey` as written in the configuration. Returns whether it
+    /// was registered.
+    fn register_hotkey(&self, id: i32, hotkey: &str) -> bool {
+        let (modifiers, vk) = match parse_hotkey(hotkey) {
+            Some(parsed) => parsed,
+            None => {
+                warn!("Invalid hotkey: {}", hotkey);
+                return false;
+            }
+        };
+        // Another program may have taken the hotkey.
+        match register_hotkey(self.hwnd, id, modifiers, vk) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed to register {}: {:?}", hotkey, e);
+                false
+            }
+        }
+    }
+
     fn set_lyrics_timer(&self) -> Result<()> {
         if unsafe { SetTimer(self.hwnd, 1, 100, None) } > 0 {
             Ok(())
@@ -134,16 +375,26 @@ impl LyricsWindow {
                 if let Some(PlayerState {
                     song_name,
                     song_artist,
+                    song_album,
+                    song_album_artist,
+                    song_duration,
+                    song_location,
                     ..
                 }) = player_state.as_ref()
                 {
-                    let mut lyrics = None;
-                    let changed = self
-                        .query
-                        .get_lyrics(song_name, song_artist, &mut lyrics)
-                        .unwrap_or(true);
-                    if changed {
-                        self.set_lyrics(lyrics).unwrap();
+                    let track = Track {
+                        name: song_name.clone(),
+                        artist: song_artist.clone(),
+                        album: song_album.clone(),
+                        album_artist: song_album_artist.clone(),
+                        duration: *song_duration,
+                        location: song_location.clone(),
+                    };
+                    if self.query.set_track(&track) {
+                        self.stop_authoring();
+                        self.user_offset = self.offsets.get(&track.key());
+                        self.track = Some(track);
+                        self.set_lyrics(None).unwrap();
                     }
                 };
                 let player_position = player_state.map(|player_state| player_state.player_position);
@@ -154,8 +405,272 @@ impl LyricsWindow {
         }
     }
 
+    fn on_lyrics_ready(
+        &mut self,
+        _hwnd: HWND,
+        _msg: u32,
+        _wparam: WPARAM,
+        _lparam: LPARAM,
+    ) -> LRESULT {
+        if let Some(lyrics) = self.query.take_lyrics() {
+            // They are looked up again if authoring is cancelled.
+            if self.authoring.is_none() {
+                self.set_lyrics(lyrics).unwrap();
+            }
+        }
+        LRESULT(1)
+    }
+
+    fn on_hotkey(&mut self, _hwnd: HWND, _msg: u32, wparam: WPARAM, _lparam: LPARAM) -> LRESULT {
+        if self.authoring.is_some() {
+            self.on_authoring_hotkey(wparam.0 as i32).unwrap();
+            return LRESULT(1);
+        }
+        let user_offset = match wparam.0 as i32 {
+            HOTKEY_OFFSET_EARLIER => self.user_offset + OFFSET_STEP,
+            HOTKEY_OFFSET_LATER => self.user_offset - OFFSET_STEP,
+            HOTKEY_OFFSET_RESET => 0,
+            HOTKEY_ANCHOR => {
+                self.anchor_line().unwrap();
+                return LRESULT(1);
+            }
+            HOTKEY_CORRECTION_DISCARD => {
+                self.discard_correction();
+                return LRESULT(1);
+            }
+            HOTKEY_AUTHORING => {
+                self.start_authoring().unwrap();
+                return LRESULT(1);
+            }
+            _ => return LRESULT(0),
+        };
+        if let Some(track) = self.track.as_ref() {
+            info!("Offset: {}ms", user_offset);
+            self.user_offset = user_offset;
+            if let Err(e) = self.offsets.set(&track.key(), user_offset) {
+                warn!("Failed to save the offset: {:?}", e);
+            }
+            self.update_lines().unwrap();
+        }
+        LRESULT(1)
+    }
+
+    /// Takes the line shown on the overlay as being sung right now. Far into
+    /// lyrics that drift, the line whose time is closest may be several lines
+    /// off, while the user tells the line they see. Once two or more lines are
+    /// anchored, the lyrics are retimed to fit them and saved as the corrected
+    /// lyrics of the track, which replace the offset.
+    fn anchor_line(&mut self) -> Result<()> {
+        let player_position = self.player_position_now();
+        let (lyrics, player_position, track, line) = match (
+            self.source.as_ref(),
+            player_position,
+            self.track.as_ref(),
+            self.karaoke_line,
+        ) {
+            (Some(lyrics), Some(player_position), Some(track), Some(line)) => {
+                (lyrics, player_position, track, line)
+            }
+            _ => return Ok(()),
+        };
+        info!("Anchor: line {} at {:?}", line, player_position);
+        // Retiming keeps the lines in order, so the indexes still hold.
+        let uncorrected = self.uncorrected.get_or_insert_with(|| lyrics.clone());
+        self.anchors.retain(|anchor| anchor.line != line);
+        self.anchors.push(Anchor {
+            line,
+            time: player_position,
+        });
+        if self.anchors.len() < 2 {
+            return Ok(());
+        }
+        let retimed = match retime(uncorrected, &self.anchors) {
+            Ok(retimed) => retimed,
+            Err(e) => {
+                warn!("Failed to retime the lyrics: {:?}", e);
+                self.anchors.pop();
+                return Ok(());
+            }
+        };
+        let key = track.key();
+        if let Err(e) = self.corrections.set(&key, Some(&retimed)) {
+            warn!("Failed to save the corrected lyrics: {:?}", e);
+        }
+        if let Err(e) = self.offsets.set(&key, 0) {
+            warn!("Failed to save the offset: {:?}", e);
+        }
+        self.user_offset = 0;
+        self.lyrics = Some(self.query.display(retimed.clone()));
+        self.source = Some(retimed);
+        self.update_lines()
+    }
+
+    /// Drops the corrected lyrics of the track, which are looked up again.
+    fn discard_correction(&mut self) {
+        if let Some(track) = self.track.as_ref() {
+            info!("Discarding the corrected lyrics");
+            if let Err(e) = self.corrections.set(&track.key(), None) {
+                warn!("Failed to discard the corrected lyrics: {:?}", e);
+            }
+            self.query.forget_track();
+        }
+    }
+
+    /// Starts syncing the lines being shown by hand, e.g. those of plain
+    /// text, from the line tapped first.
+    fn start_authoring(&mut self) -> Result<()> {
+        let authoring = match (self.source.as_ref(), self.track.as_ref()) {
+            (Some(lyrics), Some(track)) => TapSync::new(lyrics, track),
+            _ => None,
+        };
+        let authoring = match authoring {
+            Some(authoring) => authoring,
+            None => return Ok(()),
+        };
+        info!("Authoring {} lines", authoring.progress().1);
+        self.register_authoring_hotkeys(true);
+        // The taps are in the time of the track.
+        self.user_offset = 0;
+        self.lyrics = Some(self.query.display(authoring.lyrics()));
+        self.authoring = Some(authoring);
+        self.update_lines()
+    }
+
+    fn on_authoring_hotkey(&mut self, id: i32) -> Result<()> {
+        let player_position = self.player_position_now();
+        let authoring = match self.authoring.as_mut() {
+            Some(authoring) => authoring,
+            None => return Ok(()),
+        };
+        match id {
+            HOTKEY_TAP => {
+                if let Some(player_position) = player_position {
+                    authoring.tap(player_position);
+                }
+            }
+            HOTKEY_UNDO => {
+                authoring.undo();
+            }
+            // Fine-adjusts the line being shown.
+            HOTKEY_OFFSET_EARLIER | HOTKEY_OFFSET_LATER => {
+                let step = if id == HOTKEY_OFFSET_EARLIER {
+                    -FINE_ADJUST_STEP
+                } else {
+                    FINE_ADJUST_STEP
+                };
+                if let Some(player_position) = player_position {
+                    if let Some(time) = authoring.nudge(player_position, step) {
+                        info!("Line moved to {:?}", time);
+                    }
+                }
+            }
+            HOTKEY_AUTHORING => return self.finish_authoring(),
+            HOTKEY_CORRECTION_DISCARD => {
+                info!("Authoring cancelled");
+                self.stop_authoring();
+                self.query.forget_track();
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+        self.lyrics = Some(self.query.display(authoring.lyrics()));
+        self.update_lines()
+    }
+
+    /// The position of the player at the press of a hotkey, which may be up
+    /// to a tick of the timer past [`Self::player_position`].
+    fn player_position_now(&mut self) -> Option<Duration> {
+        self.player
+            .get_player_state()
+            .map(|player_state| player_state.player_position)
+    }
+
+    /// Saves the synced lines as an LRC file in the lyrics folder, or as the
+    /// corrected lyrics of the track if there is no folder or the file exists,
+    /// and shows them. Nothing is saved until every line is synced, since the
+    /// lines left out would be hidden by the saved ones.
+    fn finish_authoring(&mut self) -> Result<()> {
+        let lyrics = match self.authoring.as_ref() {
+            Some(authoring) => {
+                let (tapped, total) = authoring.progress();
+                if tapped < total {
+                    warn!("Only {} of {} lines are synced", tapped, total);
+                    return Ok(());
+                }
+                authoring.lyrics()
+            }
+            None => return Ok(()),
+        };
+        self.stop_authoring();
+        if let Some(track) = self.track.as_ref() {
+            let key = track.key();
+            let saved_to_folder = self
+                .lyrics_directory
+                .as_ref()
+                .map(|directory| save_to_folder(directory, track, &lyrics));
+            let saved = match saved_to_folder {
+                // Corrected lyrics would hide the file.
+                Some(Ok(path)) => {
+                    info!("Saved {}", path.display());
+                    self.corrections.set(&key, None)
+                }
+                Some(Err(e)) => {
+                    warn!("Failed to save to the lyrics folder: {:?}", e);
+                    self.corrections.set(&key, Some(&lyrics))
+                }
+                None => self.corrections.set(&key, Some(&lyrics)),
+            };
+            if let Err(e) = saved {
+                warn!("Failed to save the lyrics: {:?}", e);
+            }
+            if let Err(e) = self.offsets.set(&key, 0) {
+                warn!("Failed to save the offset: {:?}", e);
+            }
+        }
+        self.set_lyrics(Some(lyrics))
+    }
+
+    fn stop_authoring(&mut self) {
+        if self.authoring.take().is_some() {
+            self.register_authoring_hotkeys(false);
+        }
+    }
+
+    /// Shows the line to tap next below the line tapped last while authoring.
+    /// Returns whether there is such a line.
+    fn show_line_to_tap(&mut self) -> Result<bool> {
+        let authoring = match self.authoring.as_ref() {
+            Some(authoring) => authoring,
+            None => return Ok(false),
+        };
+        let mut line_next = match authoring.next_line() {
+            Some(line) => DisplayLine::new(line, &self.display),
+            None => return Ok(false),
+        };
+        let (tapped, total) = authoring.progress();
+        line_next
+            .secondary
+            .push(format!("{}/{}", tapped + 1, total));
+        self.line_current = authoring
+            .last_line()
+            .map(|line| DisplayLine::new(line, &self.display));
+        if self.line_next.as_ref() != Some(&line_next) {
+            self.line_next = Some(line_next.clone());
+            self.line_next_non_empty = Some(line_next);
+            self.schedule_transitions(self.line_next.as_ref())?;
+        }
+        self.in_gap = false;
+        self.karaoke_line = None;
+        self.set_karaoke(None)?;
+        Ok(true)
+    }
+
+    /// Shows `lyrics` as looked up, or nothing.
     fn set_lyrics(&mut self, lyrics: Option<Lyrics>) -> Result<()> {
-        self.lyrics = lyrics;
+        self.lyrics = lyrics.clone().map(|lyrics| self.query.display(lyrics));
+        self.source = lyrics;
+        self.uncorrected = None;
+        self.anchors.clear();
         self.set_player_position(None)
     }
 
@@ -165,17 +680,39 @@ impl LyricsWindow {
     }
 
     fn update_lines(&mut self) -> Result<()> {
+        if self.show_line_to_tap()? {
+            return Ok(());
+        }
         if let Some(lyrics) = self.lyrics.as_ref() {
             if let Some(player_position) = self.player_position {
-                let line_current = lyrics
-                    .find_timed_line_index(player_position.as_millis() as i64)
-                    .map(|index| lyrics.get_timed_lines()[index].1.to_string());
-                let line_next =
-                    lyrics
-                        .find_timed_line_index(
-                            (player_position + DURATION_SCROLLING).as_millis() as i64
-                        )
-                        .map(|index| lyrics.get_timed_lines()[index].1.to_string());
+                let position = shift(
+                    lyrics.metadata().apply_offset(player_position),
+                    self.user_offset,
+                );
+                if let Some((line, karaoke)) = self.gap_line(lyrics, position) {
+                    // Only coming into the gap scrolls; the countdown just ticks.
+                    if !self.in_gap {
+                        self.in_gap = true;
+                        self.line_next = Some(line.clone());
+                        self.line_next_non_empty = Some(line);
+                        self.schedule_transitions(self.line_next.as_ref())?;
+                    } else if self.line_next_non_empty.as_ref() != Some(&line) {
+                        self.line_next = Some(line.clone());
+                        self.line_next_non_empty = Some(line);
+                        self.draw()?;
+                    }
+                    self.karaoke_line = None;
+                    return self.set_karaoke(karaoke);
+                }
+                self.in_gap = false;
+                let min_gap = self.min_gap();
+                let index_current = lyrics.find_line_index(position, min_gap);
+                let index_next = lyrics.find_line_index(position + DURATION_SCROLLING, min_gap);
+                let display_config = &self.display;
+                let display =
+                    |index: usize| DisplayLine::new(&lyrics.lines()[index], display_config);
+                let line_current = index_current.map(display);
+                let line_next = index_next.map(display);
                 if self.line_current != line_current {
                     self.line_current = line_current;
                 }
@@ -186,7 +723,7 @@ impl LyricsWindow {
                     _ => self.line_current.as_ref(),
                 };
                 if self.line_next.as_ref() != line_next {
-                    self.line_next = line_next.map(|s| s.clone());
+                    self.line_next = line_next.cloned();
                     if !self
                         .line_next
                         .as_ref()
@@ -197,6 +734,25 @@ impl LyricsWindow {
                     }
                     self.schedule_transitions(self.line_next.as_ref())?;
                 }
+                let index_next = match index_next {
+                    Some(index) if !lyrics.lines()[index].text.is_empty() => Some(index),
+                    _ => index_current,
+                };
+                if let Some(index) = index_next {
+                    if !lyrics.lines()[index].text.is_empty() {
+                        self.karaoke_line = Some(index);
+                    }
+                }
+                // Word timings are of the original text.
+                let karaoke = self
+                    .karaoke_line
+                    .map(|index| &lyrics.lines()[index])
+                    .filter(|line| {
+                        self.display.lines != DisplayLines::Translation
+                            || line.translation.is_none()
+                    })
+                    .and_then(|line| line.progress(position));
+                self.set_karaoke(karaoke)?;
                 return Ok(());
             }
         }
@@ -204,10 +760,70 @@ impl LyricsWindow {
             self.line_next = None;
             self.schedule_transitions(None)?;
         }
+        self.in_gap = false;
+        self.karaoke_line = None;
+        self.set_karaoke(None)
+    }
+
+    /// How long a silence must last to be shown as such.
+    fn min_gap(&self) -> Duration {
+        Duration::from_secs_f64(self.display.min_gap.max(0.))
+    }
+
+    /// The indicator to show during the gap at `position`, if there is one,
+    /// with how far it has filled up.
+    fn gap_line(
+        &self,
+        lyrics: &Lyrics,
+        position: Duration,
+    ) -> Option<(DisplayLine, Option<Progress>)> {
+        let (start, end) = lyrics.find_gap(position, self.min_gap())?;
+        // The next line scrolls in as usual, so the indicator is full by then.
+        if position + DURATION_SCROLLING >= end {
+            return None;
+        }
+        let end = end - DURATION_SCROLLING;
+        let (text, fraction) = match self.display.gap_indicator {
+            GapIndicator::Off => return None,
+            GapIndicator::Note => ("\u{266a}".to_string(), None),
+            GapIndicator::Dots => {
+                let elapsed = (position - start).as_secs_f64();
+                let length = (end - start).as_secs_f64();
+                (
+                    "\u{2022} \u{2022} \u{2022}".to_string(),
+                    Some(elapsed / length),
+                )
+            }
+            GapIndicator::Countdown => {
+                let left = end - position;
+                let seconds = (left.as_secs_f64().ceil() as u64).max(1);
+                let fraction = seconds as f64 - left.as_secs_f64();
+                (seconds.to_string(), Some(fraction))
+            }
+        };
+        let karaoke = fraction.map(|fraction| Progress {
+            sung: 0,
+            singing: text.len(),
+            fraction,
+        });
+        let line = DisplayLine {
+            text,
+            annotation: None,
+            secondary: vec![],
+        };
+        Some((line, karaoke))
+    }
+
+    fn set_karaoke(&mut self, karaoke: Option<Progress>) -> Result<()> {
+        if self.karaoke != karaoke {
+            self.karaoke = karaoke;
+            // The animations don't run while a line is being sung.
+            self.draw()?;
+        }
         Ok(())
     }
 
-    fn schedule_transitions(&self, line_next: Option<&String>) -> Result<()> {
+    fn schedule_transitions(&self, line_next: Option<&DisplayLine>) -> Result<()> {
         let Resources {
             dc,
             animation_manager,
@@ -289,11 +905,11 @@ impl LyricsWindow {
                 let size = unsafe { dc.GetSize() };
                 do_transition_ease_out(opacity, DURATION_FADE_IN, None, 1., false)?;
 
-                let metrics = self.get_text_metrics(line_next, size.width, size.height)?;
+                let metrics = self.get_line_metrics(line_next, size.width, size.height)?;
                 let final_bg_width = metrics.width as f64 + 2. * PADDING_HORIZONTAL;
                 do_transition_ease_out(bg_width, DURATION_SIZING, None, final_bg_width, true)?;
 
-                let final_bg_height = metrics.height as f64 + 2. * PADDING_VERTICAL;
+                let final_bg_height = metrics.height() as f64 + 2. * PADDING_VERTICAL;
                 do_transition_ease_out(bg_height, DURATION_SIZING, None, final_bg_height, true)?;
 
                 let vertical_offset = size.height as f64 / 3.;
@@ -345,55 +961,62 @@ impl LyricsWindow {
             }
             let brush = unsafe { dc.CreateSolidColorBrush(&D2D1_COLOR_F::default(), null()) }?;
             let dwrite_factory = create_dwrite_factory()?;
-            let text_format: IDWriteTextFormat1 = unsafe {
-                dwrite_factory
-                    .CreateTextFormat(
-                        "Segoe UI",
-                        None,
-                        DWRITE_FONT_WEIGHT_NORMAL,
-                        DWRITE_FONT_STYLE_NORMAL,
-                        DWRITE_FONT_STRETCH_NORMAL,
-                        24.,
-                        "",
-                    )?
-                    .cast()
-            }?;
-            {
-                unsafe {
-                    text_format.SetTextAlignment(DWRITE_TEXT_ALIGNMENT_CENTER)?;
-                    text_format.SetParagraphAlignment(DWRITE_PARAGRAPH_ALIGNMENT_CENTER)?;
-                }
-                let font_fallback_builder = unsafe { dwrite_factory.CreateFontFallbackBuilder() }?;
-                let ranges = DWRITE_UNICODE_RANGE {
-                    first: 0x0,
-                    last: 0xffffffff,
-                };
-                let fallback_family_names = [
-                    HSTRING::from("Segoe UI Emoji"),
-                    HSTRING::from("Segoe UI Symbol"),
-                    HSTRING::from("Helvetica"),
-                    HSTRING::from("Microsoft YaHei UI"),
-                ];
-                let fallback_family_names = fallback_family_names
-                    .iter()
-                    .map(|name| name.as_wide().as_ptr())
-                    .collect::<Vec<*const u16>>();
-                unsafe {
-                    font_fallback_builder.AddMapping(
-                        &ranges,
-                        1,
-                        fallback_family_names.as_ptr(),
-                        fallback_family_names.len() as u32,
-                        None,
-                        None,
-                        None,
-                        1.,
-                    )?;
-                    font_fallback_builder.AddMappings(dwrite_factory.GetSystemFontFallback()?)?;
-                    let font_fallback = font_fallback_builder.CreateFontFallback()?;
-                    text_format.SetFontFallback(font_fallback)?;
+            let create_text_format = |font_size: f32| -> Result<IDWriteTextFormat1> {
+                let text_format: IDWriteTextFormat1 = unsafe {
+                    dwrite_factory
+                        .CreateTextFormat(
+                            "Segoe UI",
+                            None,
+                            DWRITE_FONT_WEIGHT_NORMAL,
+                            DWRITE_FONT_STYLE_NORMAL,
+                            DWRITE_FONT_STRETCH_NORMAL,
+                            font_size,
+                            "",
+                        )?
+                        .cast()
+                }?;
+                {
+                    unsafe {
+                        text_format.SetTextAlignment(DWRITE_TEXT_ALIGNMENT_CENTER)?;
+                        text_format.SetParagraphAlignment(DWRITE_PARAGRAPH_ALIGNMENT_CENTER)?;
+                    }
+                    let font_fallback_builder =
+                        unsafe { dwrite_factory.CreateFontFallbackBuilder() }?;
+                    let ranges = DWRITE_UNICODE_RANGE {
+                        first: 0x0,
+                        last: 0xffffffff,
+                    };
+                    let fallback_family_names = [
+                        HSTRING::from("Segoe UI Emoji"),
+                        HSTRING::from("Segoe UI Symbol"),
+                        HSTRING::from("Helvetica"),
+                        HSTRING::from("Microsoft YaHei UI"),
+                    ];
+                    let fallback_family_names = fallback_family_names
+                        .iter()
+                        .map(|name| name.as_wide().as_ptr())
+                        .collect::<Vec<*const u16>>();
+                    unsafe {
+                        font_fallback_builder.AddMapping(
+                            &ranges,
+                            1,
+                            fallback_family_names.as_ptr(),
+                            fallback_family_names.len() as u32,
+                            None,
+                            None,
+                            None,
+                            1.,
+                        )?;
+                        font_fallback_builder
+                            .AddMappings(dwrite_factory.GetSystemFontFallback()?)?;
+                        let font_fallback = font_fallback_builder.CreateFontFallback()?;
+                        text_format.SetFontFallback(font_fallback)?;
+                    }
                 }
-            }
+                Ok(text_format)
+            };
+            let text_format = create_text_format(24.)?;
+            let secondary_text_format = create_text_format(16.)?;
             let animation_manager = create_animation_manager()?;
             let animation_timer = create_animation_timer()?;
             let animation_timer_handler: IUIAnimationTimerEventHandler =
@@ -422,6 +1045,7 @@ impl LyricsWindow {
                 brush,
                 dwrite_factory,
                 text_format,
+                secondary_text_format,
                 animation_manager,
                 animation_timer,
                 animation_transition_library,
@@ -452,6 +1076,7 @@ impl LyricsWindow {
         let Self {
             line_current,
             line_next_non_empty,
+            karaoke,
             ..
         } = self;
         unsafe {
@@ -522,7 +1147,7 @@ impl LyricsWindow {
             let line_current_offset = line_current_offset.GetValue()? as f32;
             let line_next_offset = line_next_offset.GetValue()? as f32;
             if let Some(line_current) = line_current {
-                self.draw_text(
+                self.draw_line(
                     line_current,
                     &D2D_RECT_F {
                         left: 0.,
@@ -530,6 +1155,7 @@ impl LyricsWindow {
                         right: size.width,
                         bottom: line_current_offset + size.height,
                     },
+                    None,
                 )?;
             }
             dc.PopLayer();
@@ -551,7 +1177,7 @@ impl LyricsWindow {
                 None,
             );
             if let Some(line_next) = line_next_non_empty {
-                self.draw_text(
+                self.draw_line(
                     line_next,
                     &D2D_RECT_F {
                         left: 0.,
@@ -559,6 +1185,7 @@ impl LyricsWindow {
                         right: size.width,
                         bottom: line_next_offset + size.height,
                     },
+                    karaoke.as_ref(),
                 )?;
             }
             dc.PopLayer();
@@ -572,14 +1199,11 @@ impl LyricsWindow {
     fn create_text_layout(
         &self,
         text: &str,
+        text_format: &IDWriteTextFormat1,
         max_width: f32,
         max_height: f32,
     ) -> Result<IDWriteTextLayout> {
-        let Resources {
-            dwrite_factory,
-            text_format,
-            ..
-        } = self.get_or_init_resources()?;
+        let Resources { dwrite_factory, .. } = self.get_or_init_resources()?;
         let string = HSTRING::from(text);
         unsafe {
             dwrite_factory
@@ -594,26 +1218,140 @@ impl LyricsWindow {
         }
     }
 
-    fn draw_text(&self, text: &str, rect: &D2D_RECT_F) -> Result<()> {
+    /// Draws `line` centered in `rect`, with its annotation above it and its
+    /// secondary texts below it.
+    fn draw_line(
+        &self,
+        line: &DisplayLine,
+        rect: &D2D_RECT_F,
+        karaoke: Option<&Progress>,
+    ) -> Result<()> {
+        let Resources {
+            text_format,
+            secondary_text_format,
+            ..
+        } = self.get_or_init_resources()?;
+        let metrics =
+            self.get_line_metrics(line, rect.right - rect.left, rect.bottom - rect.top)?;
+        // Each text is centered vertically in `rect`; move them so that they
+        // are stacked and centered together.
+        let mut top = -metrics.height() / 2.;
+        for ((text, primary), height) in line.parts().into_iter().zip(metrics.heights.iter()) {
+            let shift = top + height / 2.;
+            let (text_format, karaoke) = if primary {
+                (text_format, karaoke)
+            } else {
+                (secondary_text_format, None)
+            };
+            self.draw_text(
+                text,
+                text_format,
+                &D2D_RECT_F {
+                    left: rect.left,
+                    top: rect.top + shift,
+                    right: rect.right,
+                    bottom: rect.bottom + shift,
+                },
+                karaoke,
+            )?;
+            top += height;
+        }
+        Ok(())
+    }
+
+    /// Draws `text` in white, or with the part sung so far in white and the
+    /// rest dimmed if `karaoke` is given. Lyrics with estimated times are
+    /// dimmed as a whole.
+    fn draw_text(
+        &self,
+        text: &str,
+        text_format: &IDWriteTextFormat1,
+        rect: &D2D_RECT_F,
+        karaoke: Option<&Progress>,
+    ) -> Result<()> {
         let Resources { dc, brush, .. } = self.get_or_init_resources()?;
-        let text_layout =
-            self.create_text_layout(text, rect.right - rect.left, rect.bottom - rect.top)?;
+        let max_width = rect.right - rect.left;
+        let max_height = rect.bottom - rect.top;
+        let text_layout = self.create_text_layout(text, text_format, max_width, max_height)?;
+        let origin = D2D_POINT_2F {
+            x: rect.left,
+            y: rect.top,
+        };
+        let karaoke = match karaoke {
+            Some(karaoke) => karaoke,
+            None => {
+                let estimated = self
+                    .lyrics
+                    .as_ref()
+                    .map(|lyrics| lyrics.metadata().estimated)
+                    .unwrap_or(false);
+                unsafe {
+                    brush.SetColor(&D2D1_COLOR_F {
+                        r: 1.,
+                        g: 1.,
+                        b: 1.,
+                        a: if estimated {
+                            ESTIMATED_TEXT_OPACITY
+                        } else {
+                            1.
+                        },
+                    });
+                    dc.DrawTextLayout(
+                        origin,
+                        &text_layout,
+                        brush,
+                        D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+                    );
+                }
+                return Ok(());
+            }
+        };
+        // The fill follows the width of the text sung so far, which is exact
+        // as long as the line doesn't wrap.
+        let left = unsafe { text_layout.GetMetrics() }?.left;
+        let sung_width = |length: usize| -> Result<f32> {
+            Ok(self
+                .get_text_metrics(&text[..length], text_format, max_width, max_height)?
+                .widthIncludingTrailingWhitespace)
+        };
+        let sung = sung_width(karaoke.sung)?;
+        let singing = sung_width(karaoke.singing)?;
+        let fill = rect.left + left + sung + (singing - sung) * karaoke.fraction as f32;
         unsafe {
             brush.SetColor(&D2D1_COLOR_F {
                 r: 1.,
                 g: 1.,
                 b: 1.,
-                a: 1.,
+                a: 0.5,
             });
             dc.DrawTextLayout(
-                &D2D_POINT_2F {
-                    x: rect.left,
-                    y: rect.top,
+                origin,
+                &text_layout,
+                brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+            dc.PushAxisAlignedClip(
+                &D2D_RECT_F {
+                    left: rect.left,
+                    top: rect.top,
+                    right: fill,
+                    bottom: rect.bottom,
                 },
+                D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+            );
+            brush.SetColor(&D2D1_COLOR_F {
+                r: 1.,
+                g: 1.,
+                b: 1.,
+                a: 1.,
+            });
+            dc.DrawTextLayout(
+                origin,
                 &text_layout,
                 brush,
                 D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
             );
+            dc.PopAxisAlignedClip();
         }
         Ok(())
     }
@@ -621,12 +1359,41 @@ impl LyricsWindow {
     fn get_text_metrics(
         &self,
         text: &str,
+        text_format: &IDWriteTextFormat1,
         max_width: f32,
         max_height: f32,
     ) -> Result<DWRITE_TEXT_METRICS> {
-        let text_layout = self.create_text_layout(text, max_width, max_height)?;
+        let text_layout = self.create_text_layout(text, text_format, max_width, max_height)?;
         unsafe { text_layout.GetMetrics() }.map_err(|e| e.into())
     }
+
+    fn get_line_metrics(
+        &self,
+        line: &DisplayLine,
+        max_width: f32,
+        max_height: f32,
+    ) -> Result<LineMetrics> {
+        let Resources {
+            text_format,
+            secondary_text_format,
+            ..
+        } = self.get_or_init_resources()?;
+        let mut metrics = LineMetrics {
+            width: 0.,
+            heights: vec![],
+        };
+        for (text, primary) in line.parts() {
+            let text_format = if primary {
+                text_format
+            } else {
+                secondary_text_format
+            };
+            let text = self.get_text_metrics(text, text_format, max_width, max_height)?;
+            metrics.width = metrics.width.max(text.width);
+            metrics.heights.push(text.height);
+        }
+        Ok(metrics)
+    }
 }
 
 #[implement(Windows::Win32::UI::Animation::IUIAnimationTimerEventHandler)]
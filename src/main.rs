@@ -1,25 +1,36 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cli;
+mod config;
 mod initialize;
 mod lyrics;
 mod lyrics_window;
+mod paths;
 mod player;
 mod types;
 mod ui;
 
+use std::env;
+
 use anyhow::Result;
 use log::error;
 use log::info;
 
+use config::Config;
 use initialize::initialize;
 use lyrics_window::LyricsWindow;
 use ui::run_message_loop;
 
 fn main() -> Result<()> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        return cli::run(&args);
+    }
     let run = || -> Result<()> {
         initialize()?;
         info!("Initialized");
-        let lyrics_window = &mut LyricsWindow::new()?;
+        let config = Config::load()?;
+        let lyrics_window = &mut LyricsWindow::new(&config)?;
         lyrics_window.show()?;
         run_message_loop();
         Ok(())
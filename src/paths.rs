@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+/// The root directory of iLyrics' data, i.e. `%APPDATA%/iLyrics` on Windows.
+pub fn data_directory() -> PathBuf {
+    dirs::data_dir().unwrap_or_default().join("iLyrics")
+}
+
+/// The directory of the lyrics cache.
+///
+/// It lives under [`data_directory`] on Windows and follows the XDG base
+/// directory specification on Linux, e.g. `~/.cache/ilyrics`.
+pub fn cache_directory() -> PathBuf {
+    if cfg!(windows) {
+        data_directory().join("cache")
+    } else {
+        dirs::cache_dir().unwrap_or_default().join("ilyrics")
+    }
+}
+
+/// The file of the timing corrections made by the user, kept apart from the
+/// cache so that they are never evicted.
+pub fn offsets_file() -> PathBuf {
+    data_directory().join("offsets.json")
+}
+
+/// The directory of the lyrics retimed or synced by the user, which are
+/// theirs rather than a cache.
+pub fn corrections_directory() -> PathBuf {
+    data_directory().join("corrections")
+}
+
+/// The path of the user configuration file, e.g. `~/.config/ilyrics/config.toml`
+/// on Linux.
+pub fn config_file() -> PathBuf {
+    if cfg!(windows) {
+        data_directory().join("config.toml")
+    } else {
+        dirs::config_dir()
+            .unwrap_or_default()
+            .join("ilyrics")
+            .join("config.toml")
+    }
+}
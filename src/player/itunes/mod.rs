@@ -1,11 +1,13 @@
 use std::intrinsics::transmute;
 use std::mem;
 use std::mem::transmute_copy;
+use std::path::PathBuf;
 use std::ptr::null_mut;
 use std::ptr::NonNull;
 use std::time::Duration;
 use std::time::SystemTime;
 
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use bindings::Windows::Win32::Foundation::*;
@@ -43,6 +45,10 @@ impl Player for ITunes {
             player_position.map(|player_position| PlayerState {
                 song_name: track_info.name,
                 song_artist: track_info.artist,
+                song_album: track_info.album,
+                song_album_artist: track_info.album_artist,
+                song_duration: track_info.duration,
+                song_location: track_info.location,
                 player_position,
             })
         })
@@ -87,7 +93,37 @@ impl ITunes {
                     (|| -> Result<TrackInfo> {
                         let name = track.GetName()?.to_string();
                         let artist = track.GetArtist()?.to_string();
-                        Ok(TrackInfo { name, artist })
+                        let album = track
+                            .GetAlbum()
+                            .map(|album| album.to_string())
+                            .unwrap_or_default();
+                        let duration = track
+                            .GetDuration()
+                            .ok()
+                            .filter(|seconds| *seconds > 0)
+                            .map(|seconds| Duration::from_secs(seconds as u64));
+                        // Only tracks backed by files or CDs have an album artist
+                        // and a location.
+                        let file_track = track.cast::<IITFileOrCDTrack>().ok();
+                        let album_artist = file_track
+                            .as_ref()
+                            .and_then(|track| track.GetAlbumArtist().ok())
+                            .map(|album_artist| album_artist.to_string())
+                            .unwrap_or_default();
+                        let location = file_track
+                            .as_ref()
+                            .and_then(|track| track.GetLocation().ok())
+                            .map(|location| location.to_string())
+                            .filter(|location| !location.is_empty())
+                            .map(PathBuf::from);
+                        Ok(TrackInfo {
+                            name,
+                            artist,
+                            album,
+                            album_artist,
+                            duration,
+                            location,
+                        })
                     })()
                     .map(Some)
                     .unwrap_or(None)
@@ -95,6 +131,17 @@ impl ITunes {
         }
     }
 
+    /// Makes iTunes re-read the tags of the current track from its file.
+    pub fn update_current_track_from_file(&self) -> Result<()> {
+        unsafe {
+            self.get_instance()
+                .GetCurrentTrack()?
+                .ok_or_else(|| anyhow!("No track in iTunes"))?
+                .cast::<IITFileOrCDTrack>()?
+                .UpdateInfoFromFile()
+        }
+    }
+
     pub fn get_player_position(&mut self) -> Option<Duration> {
         let player_position = unsafe {
             self.get_instance()
@@ -131,6 +178,10 @@ impl Drop for ITunes {
 pub struct TrackInfo {
     pub name: String,
     pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub duration: Option<Duration>,
+    pub location: Option<PathBuf>,
 }
 
 #[repr(transparent)]
@@ -304,9 +355,25 @@ struct IITTrack_abi(
     pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
     pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
     pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
-    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr, value: *mut *mut u16) -> HRESULT,
     pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
     pub unsafe extern "system" fn(this: RawPtr, value: *mut *mut u16) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr, value: *mut i32) -> HRESULT,
 );
 
 unsafe impl Interface for IITTrack {
@@ -329,12 +396,177 @@ impl IITTrack {
             .context("Failed to GetName")
     }
 
+    pub unsafe fn GetAlbum(&self) -> Result<BSTR> {
+        let mut abi: <BSTR as Abi>::Abi = mem::zeroed();
+        (Interface::vtable(self).20)(Abi::abi(self), &mut abi)
+            .from_abi(abi)
+            .context("Failed to GetAlbum")
+    }
+
     pub unsafe fn GetArtist(&self) -> Result<BSTR> {
         let mut abi: <BSTR as Abi>::Abi = mem::zeroed();
         (Interface::vtable(self).22)(Abi::abi(self), &mut abi)
             .from_abi(abi)
             .context("Failed to GetArtist")
     }
+
+    /// The length of the track in seconds.
+    pub unsafe fn GetDuration(&self) -> Result<i32> {
+        let mut value: i32 = 0;
+        (Interface::vtable(self).38)(Abi::abi(self), &mut value)
+            .ok()
+            .context("Failed to GetDuration")?;
+        Ok(value)
+    }
+}
+
+#[repr(transparent)]
+#[derive(Clone, PartialEq, Eq)]
+struct IITFileOrCDTrack(IUnknown);
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct IITFileOrCDTrack_abi(
+    // IUnknown
+    pub unsafe extern "system" fn(this: RawPtr, iid: *const Guid, interface: *mut RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> u32,
+    pub unsafe extern "system" fn(this: RawPtr) -> u32,
+    // IDispatch (TODO)
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    // IITObject
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr, value: *mut *mut u16) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    // IITTrack
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr, value: *mut *mut u16) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    // IITFileOrCDTrack
+    pub unsafe extern "system" fn(this: RawPtr, value: *mut *mut u16) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr) -> HRESULT,
+    pub unsafe extern "system" fn(this: RawPtr, value: *mut *mut u16) -> HRESULT,
+);
+
+unsafe impl Interface for IITFileOrCDTrack {
+    type Vtable = IITFileOrCDTrack_abi;
+
+    const IID: Guid = Guid::from_values(
+        0x00d7_fe99,
+        0x7868,
+        0x4cc7,
+        [0xad, 0x9e, 0xac, 0xfd, 0x70, 0xd0, 0x95, 0x66],
+    );
+}
+
+#[allow(non_snake_case)]
+impl IITFileOrCDTrack {
+    /// The path of the audio file, empty if it is missing.
+    pub unsafe fn GetLocation(&self) -> Result<BSTR> {
+        let mut abi: <BSTR as Abi>::Abi = mem::zeroed();
+        (Interface::vtable(self).72)(Abi::abi(self), &mut abi)
+            .from_abi(abi)
+            .context("Failed to GetLocation")
+    }
+
+    /// Re-reads the tags of the audio file, e.g. after lyrics are written.
+    pub unsafe fn UpdateInfoFromFile(&self) -> Result<()> {
+        (Interface::vtable(self).73)(Abi::abi(self))
+            .ok()
+            .context("Failed to UpdateInfoFromFile")
+    }
+
+    pub unsafe fn GetAlbumArtist(&self) -> Result<BSTR> {
+        let mut abi: <BSTR as Abi>::Abi = mem::zeroed();
+        (Interface::vtable(self).98)(Abi::abi(self), &mut abi)
+            .from_abi(abi)
+            .context("Failed to GetAlbumArtist")
+    }
 }
 
 // TODO: Rewrite with windows::implement once it is ready.
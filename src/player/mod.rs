@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::time::Duration;
 
 pub mod itunes;
@@ -10,5 +11,11 @@ pub trait Player {
 pub struct PlayerState {
     pub song_name: String,
     pub song_artist: String,
+    pub song_album: String,
+    /// Empty if unknown.
+    pub song_album_artist: String,
+    pub song_duration: Option<Duration>,
+    /// The audio file, if the track is backed by one.
+    pub song_location: Option<PathBuf>,
     pub player_position: Duration,
 }
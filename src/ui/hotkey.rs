@@ -0,0 +1,99 @@
+use bindings::Windows::Win32::Foundation::*;
+use bindings::Windows::Win32::UI::KeyboardAndMouseInput::*;
+use bindings::Windows::Win32::UI::WindowsAndMessaging::*;
+use windows::*;
+
+/// Registers a system-wide hotkey that posts `WM_HOTKEY` with `id` to `hwnd`.
+///
+/// The lyrics window never takes the focus, so this is the only way for it
+/// to receive key presses.
+pub fn register_hotkey(hwnd: HWND, id: i32, modifiers: HOT_KEY_MODIFIERS, vk: u32) -> Result<()> {
+    if unsafe { RegisterHotKey(hwnd, id, modifiers, vk) }.as_bool() {
+        Ok(())
+    } else {
+        Err(HRESULT::from_thread().into())
+    }
+}
+
+/// Unregisters a hotkey registered with [`register_hotkey`].
+pub fn unregister_hotkey(hwnd: HWND, id: i32) -> Result<()> {
+    if unsafe { UnregisterHotKey(hwnd, id) }.as_bool() {
+        Ok(())
+    } else {
+        Err(HRESULT::from_thread().into())
+    }
+}
+
+/// Parses a hotkey like `Ctrl+Alt+Left` into its modifiers and virtual-key
+/// code, regardless of case. See [`HotkeysConfig`](crate::config::HotkeysConfig).
+pub fn parse_hotkey(hotkey: &str) -> Option<(HOT_KEY_MODIFIERS, u32)> {
+    let mut parts = hotkey.split('+').map(str::trim).collect::<Vec<_>>();
+    let key = parts.pop()?.to_ascii_uppercase();
+    // Held down, a hotkey would fire over and over.
+    let mut modifiers = MOD_NOREPEAT;
+    for part in parts.iter() {
+        modifiers |= match part.to_ascii_uppercase().as_str() {
+            "CTRL" | "CONTROL" => MOD_CONTROL,
+            "ALT" => MOD_ALT,
+            "SHIFT" => MOD_SHIFT,
+            "WIN" => MOD_WIN,
+            _ => return None,
+        };
+    }
+    let vk = match key.as_str() {
+        "LEFT" => VK_LEFT,
+        "RIGHT" => VK_RIGHT,
+        "UP" => VK_UP,
+        "DOWN" => VK_DOWN,
+        "SPACE" => VK_SPACE,
+        "BACKSPACE" => VK_BACK,
+        "ENTER" => VK_RETURN,
+        "TAB" => VK_TAB,
+        "ESC" => VK_ESCAPE,
+        "INSERT" => VK_INSERT,
+        "DELETE" => VK_DELETE,
+        "HOME" => VK_HOME,
+        "END" => VK_END,
+        "PAGEUP" => VK_PRIOR,
+        "PAGEDOWN" => VK_NEXT,
+        // The codes of letters and digits are their ASCII codes.
+        _ if key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            key.chars().next()? as u32
+        }
+        _ => match key.strip_prefix('F')?.parse::<u32>().ok()? {
+            n @ 1..=24 => VK_F1 + n - 1,
+            _ => return None,
+        },
+    };
+    Some((modifiers, vk))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hotkeys() {
+        assert_eq!(
+            parse_hotkey("Ctrl+Alt+Shift+Left"),
+            Some((MOD_CONTROL | MOD_ALT | MOD_SHIFT | MOD_NOREPEAT, VK_LEFT))
+        );
+        assert_eq!(
+            parse_hotkey("ctrl + alt + s"),
+            Some((MOD_CONTROL | MOD_ALT | MOD_NOREPEAT, 0x53))
+        );
+        assert_eq!(parse_hotkey("Win+0"), Some((MOD_WIN | MOD_NOREPEAT, 0x30)));
+        assert_eq!(
+            parse_hotkey("Alt+F12"),
+            Some((MOD_ALT | MOD_NOREPEAT, VK_F12))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(parse_hotkey("Ctrl+Alt+F25"), None);
+        assert_eq!(parse_hotkey("Ctrl+Meta+Left"), None);
+        assert_eq!(parse_hotkey("Ctrl+Alt+"), None);
+        assert_eq!(parse_hotkey("Ctrl+Alt+Left+Right"), None);
+    }
+}
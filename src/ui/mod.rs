@@ -1,3 +1,4 @@
+pub mod hotkey;
 mod message;
 pub mod utils;
 mod window;